@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+
+use crate::state::CapConfig;
+
+/// What an advisory warns about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdvisoryKind {
+    Vulnerability,
+    Unsound,
+    Unmaintained,
+    Notice,
+}
+
+impl Display for AdvisoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Vulnerability => write!(f, "vulnerability"),
+            Self::Unsound => write!(f, "unsound"),
+            Self::Unmaintained => write!(f, "unmaintained"),
+            Self::Notice => write!(f, "notice"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    package: String,
+    informational: Option<String>,
+    withdrawn: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AdvisoryVersions {
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+/// The TOML front matter of an advisory file.
+#[derive(Debug, Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+/// A RustSec advisory for a crate.
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    id: String,
+    package: String,
+    title: String,
+    kind: AdvisoryKind,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    /// Parse an advisory from its markdown file.
+    ///
+    /// The metadata is in a fenced TOML block at the start of the file,
+    /// followed by the title as the first heading.
+    fn parse(advisory_str: &str) -> anyhow::Result<Option<Self>> {
+        let front_matter = advisory_str
+            .trim_start()
+            .strip_prefix("```toml")
+            .and_then(|rest| rest.split_once("\n```"))
+            .map(|(front_matter, _)| front_matter)
+            .context("Advisory has no TOML front matter.")?;
+        let file: AdvisoryFile = toml::from_str(front_matter)?;
+        if file.advisory.withdrawn.is_some() {
+            return Ok(None);
+        }
+
+        let kind = match file.advisory.informational.as_deref() {
+            None => AdvisoryKind::Vulnerability,
+            Some("unsound") => AdvisoryKind::Unsound,
+            Some("unmaintained") => AdvisoryKind::Unmaintained,
+            Some(_) => AdvisoryKind::Notice,
+        };
+        let title = advisory_str
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        Ok(Some(Self {
+            id: file.advisory.id,
+            package: file.advisory.package,
+            title,
+            kind,
+            patched: file.versions.patched,
+            unaffected: file.versions.unaffected,
+        }))
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn kind(&self) -> AdvisoryKind {
+        self.kind
+    }
+
+    pub fn patched(&self) -> &[VersionReq] {
+        &self.patched
+    }
+
+    /// Whether a version of the crate is affected.
+    ///
+    /// Versions that are neither patched nor unaffected are affected,
+    /// so advisories without patched versions affect every version.
+    pub fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|requirement| requirement.matches(version))
+    }
+}
+
+/// A local checkout of the RustSec advisory database.
+///
+/// Advisories are stored as `crates/<crate>/<id>.md`.
+#[derive(Debug)]
+pub struct AdvisoryDb {
+    advisories: BTreeMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDb {
+    /// Get the path of the advisory database.
+    ///
+    /// This is `audit.advisory_db` from the cap config, or the checkout
+    /// cargo-audit keeps in the cargo home dir.
+    pub fn default_path() -> anyhow::Result<PathBuf> {
+        if let Some(path) = CapConfig::load()?.audit.advisory_db {
+            return Ok(path);
+        }
+        Ok(home::cargo_home()
+            .context("Unable to find cargo home dir.")?
+            .join("advisory-db"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let crates_dir = path.join("crates");
+        if !crates_dir.is_dir() {
+            anyhow::bail!(
+                "No advisory database found at {}. Clone it with: git clone https://github.com/rustsec/advisory-db {}",
+                path.display(),
+                path.display()
+            );
+        }
+
+        let mut advisories: BTreeMap<String, Vec<Advisory>> = BTreeMap::new();
+        for crate_dir in std::fs::read_dir(&crates_dir)? {
+            let crate_dir = crate_dir?.path();
+            if !crate_dir.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(&crate_dir)? {
+                let advisory_path = entry?.path();
+                if advisory_path.extension().is_none_or(|ext| ext != "md") {
+                    continue;
+                }
+                // A single broken advisory must not hide all the others
+                let advisory = read_to_string(&advisory_path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|advisory_str| Advisory::parse(&advisory_str));
+                match advisory {
+                    Ok(Some(advisory)) => advisories
+                        .entry(advisory.package.clone())
+                        .or_default()
+                        .push(advisory),
+                    Ok(None) => (),
+                    Err(err) => {
+                        eprintln!("Skipping advisory {}: {:#}", advisory_path.display(), err)
+                    }
+                }
+            }
+        }
+        for crate_advisories in advisories.values_mut() {
+            crate_advisories.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+
+        Ok(Self { advisories })
+    }
+
+    /// Get the advisories that affect a version of a crate.
+    pub fn affecting(&self, crate_name: &str, version: &Version) -> Vec<&Advisory> {
+        self.advisories
+            .get(crate_name)
+            .into_iter()
+            .flatten()
+            .filter(|advisory| advisory.affects(version))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADVISORY: &str = r#"```toml
+[advisory]
+id = "RUSTSEC-2021-0001"
+package = "foo"
+date = "2021-01-01"
+
+[versions]
+patched = [">= 1.2.0"]
+unaffected = ["< 0.5.0"]
+```
+
+# Out of bounds read in foo
+
+Details.
+"#;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn parse_advisory() {
+        let advisory = Advisory::parse(ADVISORY).unwrap().unwrap();
+        assert_eq!(advisory.id(), "RUSTSEC-2021-0001");
+        assert_eq!(advisory.package, "foo");
+        assert_eq!(advisory.title(), "Out of bounds read in foo");
+        assert_eq!(advisory.kind(), AdvisoryKind::Vulnerability);
+        assert!(advisory.affects(&version("1.1.0")));
+        assert!(!advisory.affects(&version("1.2.0")));
+        assert!(!advisory.affects(&version("0.4.0")));
+    }
+
+    #[test]
+    fn parse_informational_advisories() {
+        let kind = |informational: &str| {
+            let advisory_str = ADVISORY.replace(
+                "package = \"foo\"",
+                &format!("package = \"foo\"\ninformational = \"{}\"", informational),
+            );
+            Advisory::parse(&advisory_str).unwrap().unwrap().kind()
+        };
+        assert_eq!(kind("unsound"), AdvisoryKind::Unsound);
+        assert_eq!(kind("unmaintained"), AdvisoryKind::Unmaintained);
+        assert_eq!(kind("notice"), AdvisoryKind::Notice);
+    }
+
+    #[test]
+    fn parse_skips_withdrawn_advisories() {
+        let advisory_str = ADVISORY.replace(
+            "date = \"2021-01-01\"",
+            "date = \"2021-01-01\"\nwithdrawn = \"2021-02-01\"",
+        );
+        assert!(Advisory::parse(&advisory_str).unwrap().is_none());
+    }
+
+    #[test]
+    fn advisories_without_patched_versions_affect_everything() {
+        let advisory_str = ADVISORY
+            .replace("patched = [\">= 1.2.0\"]", "")
+            .replace("unaffected = [\"< 0.5.0\"]", "");
+        let advisory = Advisory::parse(&advisory_str).unwrap().unwrap();
+        assert!(advisory.affects(&version("0.1.0")));
+        assert!(advisory.affects(&version("99.0.0")));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_advisories() {
+        assert!(Advisory::parse("# No front matter").is_err());
+        assert!(Advisory::parse("```toml\n[advisory]\nid = 1\n```\n").is_err());
+    }
+
+    #[test]
+    fn load_skips_malformed_advisories() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let foo_dir = db_dir.path().join("crates").join("foo");
+        std::fs::create_dir_all(&foo_dir).unwrap();
+        std::fs::write(foo_dir.join("RUSTSEC-2021-0001.md"), ADVISORY).unwrap();
+        std::fs::write(foo_dir.join("RUSTSEC-2021-0002.md"), "```toml\nbroken").unwrap();
+        std::fs::write(foo_dir.join("README.txt"), "Not an advisory").unwrap();
+
+        let db = AdvisoryDb::load(db_dir.path()).unwrap();
+        let affecting = db.affecting("foo", &version("1.0.0"));
+        assert_eq!(affecting.len(), 1);
+        assert_eq!(affecting[0].id(), "RUSTSEC-2021-0001");
+        assert!(db.affecting("foo", &version("1.2.0")).is_empty());
+        assert!(db.affecting("bar", &version("1.0.0")).is_empty());
+    }
+
+    #[test]
+    fn load_requires_a_crates_dir() {
+        let db_dir = tempfile::tempdir().unwrap();
+        assert!(AdvisoryDb::load(db_dir.path()).is_err());
+    }
+}
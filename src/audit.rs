@@ -0,0 +1,232 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+use semver::Version;
+use serde::Deserialize;
+
+use crate::{
+    advisory_db::{Advisory, AdvisoryDb, AdvisoryKind},
+    local_crates::cargo_bin_dir,
+    CratesRegistry, Package, PackageTree,
+};
+
+/// A crate in the dependency tree that `cargo auditable` embeds in a binary.
+#[derive(Debug, Deserialize)]
+struct EmbeddedPackage {
+    name: String,
+    version: Version,
+    source: String,
+    #[serde(default)]
+    root: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddedDependencies {
+    packages: Vec<EmbeddedPackage>,
+}
+
+/// Read the crates.io dependencies embedded in a binary by `cargo auditable`.
+///
+/// Returns `None` if the binary was built without `cargo auditable`.
+fn embedded_dependencies(binary_path: &Path) -> anyhow::Result<Option<Vec<(String, Version)>>> {
+    let json = match auditable_info::json_from_file(binary_path, Default::default()) {
+        Ok(json) => json,
+        Err(auditable_info::Error::NoAuditData) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let dependencies: EmbeddedDependencies = serde_json::from_str(&json).with_context(|| {
+        format!(
+            "Unable to parse dependency data of {}.",
+            binary_path.display()
+        )
+    })?;
+    Ok(Some(
+        dependencies
+            .packages
+            .into_iter()
+            .filter(|package| !package.root && package.source == "crates.io")
+            .map(|package| (package.name, package.version))
+            .collect(),
+    ))
+}
+
+pub struct PackageAuditor<'a> {
+    registry: &'a CratesRegistry,
+    packages: &'a PackageTree,
+}
+
+impl<'a> PackageAuditor<'a> {
+    pub fn new(registry: &'a CratesRegistry, packages: &'a PackageTree) -> Self {
+        Self { registry, packages }
+    }
+
+    /// Check the installed packages against the RustSec advisory database.
+    ///
+    /// Packages from crates.io are checked themselves, as the advisories refer
+    /// to crates published there. With `deep`, the dependencies embedded in the
+    /// binaries of all packages are checked too. Returns `true` if any advisory
+    /// affects the packages.
+    pub fn audit(&self, db_path: Option<PathBuf>, deep: bool) -> anyhow::Result<bool> {
+        let db_path = match db_path {
+            Some(db_path) => db_path,
+            None => AdvisoryDb::default_path()?,
+        };
+        let db = AdvisoryDb::load(&db_path)?;
+        let bin_dir = cargo_bin_dir()?;
+
+        let audited_packages = self
+            .packages
+            .packages()
+            .filter(|package| deep || package.source().is_crates_io())
+            .collect::<Vec<_>>();
+        let mut affected_count = 0;
+        let mut unauditable_binaries: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for package in &audited_packages {
+            let advisories = if package.source().is_crates_io() {
+                db.affecting(package.name(), package.version())
+            } else {
+                Vec::new()
+            };
+
+            let mut dependencies = BTreeSet::new();
+            if deep {
+                for binary in package.binaries() {
+                    match embedded_dependencies(&bin_dir.join(binary.name())) {
+                        Ok(Some(binary_dependencies)) => dependencies.extend(binary_dependencies),
+                        Ok(None) => unauditable_binaries
+                            .entry(package.name())
+                            .or_default()
+                            .push(binary.name()),
+                        Err(err) => {
+                            println!(
+                                "{} to audit binary {}: {}",
+                                "Failed".yellow(),
+                                binary.name(),
+                                err
+                            );
+                            unauditable_binaries
+                                .entry(package.name())
+                                .or_default()
+                                .push(binary.name());
+                        }
+                    }
+                }
+            }
+            let affected_dependencies = dependencies
+                .iter()
+                .map(|(name, version)| (name, version, db.affecting(name, version)))
+                .filter(|(_, _, advisories)| !advisories.is_empty())
+                .collect::<Vec<_>>();
+
+            if advisories.is_empty() && affected_dependencies.is_empty() {
+                continue;
+            }
+            affected_count += 1;
+            println!(
+                "{} {}",
+                package.name().blue(),
+                package.version().to_string().bright_black()
+            );
+            if !advisories.is_empty() {
+                for advisory in &advisories {
+                    print_advisory(advisory, 2);
+                }
+                match self.fixed_version(package, &db) {
+                    Some(version) if version > *package.version() => {
+                        println!("    Update to {}", version.green())
+                    }
+                    _ => println!("    {}", "No fixed version available.".yellow()),
+                }
+            }
+            for (name, version, advisories) in affected_dependencies {
+                println!(
+                    "  Contains {} {}",
+                    name.magenta(),
+                    version.to_string().bright_black()
+                );
+                for advisory in advisories {
+                    print_advisory(advisory, 4);
+                }
+            }
+        }
+
+        if !unauditable_binaries.is_empty() {
+            println!("Binaries without dependency data, built without cargo auditable:");
+            for (package_name, binaries) in &unauditable_binaries {
+                println!(
+                    "  {}: {}",
+                    package_name.blue(),
+                    binaries.join(", ").bright_black()
+                );
+            }
+        }
+
+        let package_count_text = format!(
+            "{} {}",
+            audited_packages.len(),
+            crate::helper::pluralize("package", "packages", audited_packages.len())
+        );
+        if affected_count == 0 {
+            println!(
+                "{} affect the {} checked.",
+                "No advisories".green(),
+                package_count_text
+            );
+            return Ok(false);
+        }
+        println!(
+            "{} of {} affected by advisories.",
+            affected_count.red(),
+            package_count_text
+        );
+        Ok(true)
+    }
+
+    /// Get the latest version of a package that no advisory affects.
+    fn fixed_version(&self, package: &Package, db: &AdvisoryDb) -> Option<Version> {
+        let crate_ = self.registry.get_crate(package.name()).ok()?;
+        crate_
+            .versions()
+            .iter()
+            .filter(|version| !version.is_yanked())
+            .filter_map(|version| Version::parse(version.version()).ok())
+            .filter(|version| version.pre.is_empty())
+            .filter(|version| db.affecting(package.name(), version).is_empty())
+            .max()
+    }
+}
+
+fn print_advisory(advisory: &Advisory, indent: usize) {
+    let kind_text = match advisory.kind() {
+        AdvisoryKind::Vulnerability => advisory.kind().red().to_string(),
+        _ => advisory.kind().yellow().to_string(),
+    };
+    println!(
+        "{:indent$}{} {}: {}",
+        "",
+        advisory.id().cyan(),
+        kind_text,
+        advisory.title(),
+        indent = indent
+    );
+    let patched_text = if advisory.patched().is_empty() {
+        "none".to_string()
+    } else {
+        advisory
+            .patched()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    println!(
+        "{:indent$}Patched versions: {}",
+        "",
+        patched_text.bright_black(),
+        indent = indent + 2
+    );
+}
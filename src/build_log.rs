@@ -0,0 +1,285 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+use time::{macros::format_description, OffsetDateTime, PrimitiveDateTime};
+
+/// Format of the timestamp at the start of each log file name.
+const TIMESTAMP_FORMAT: &[time::format_description::FormatItem<'static>] =
+    format_description!("[year]-[month]-[day]T[hour]-[minute]-[second]-[subsecond digits:3]");
+
+/// Format of log timestamps shown to the user.
+const DISPLAY_FORMAT: &[time::format_description::FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second] UTC");
+
+/// Turn a package name into a name that is safe to use as a single dir.
+///
+/// Package names from crates.io are kept as they are. Anything else that
+/// could escape the logs dir, like `/` or `..`, is replaced.
+fn dir_name(package_name: &str) -> String {
+    let name = package_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    }
+}
+
+/// A file that captures the output of a single cargo invocation.
+///
+/// Logs are stored per package in the cap state dir, as
+/// `logs/<package>/<timestamp>-<action>.log`.
+#[derive(Debug)]
+pub struct BuildLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl BuildLog {
+    /// Create a new log file for an attempt to build a package.
+    pub fn create(package_name: &str, action: &str) -> anyhow::Result<Self> {
+        let dir = Self::dir(package_name)?;
+        std::fs::create_dir_all(&dir).context("Unable to create build log dir.")?;
+
+        // Retry with a fresh timestamp in the unlikely case of a collision
+        loop {
+            let timestamp = OffsetDateTime::now_utc().format(TIMESTAMP_FORMAT)?;
+            let path = dir.join(format!("{}-{}.log", timestamp, action));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok(Self { path, file }),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(err) => return Err(err).context("Unable to create build log."),
+            }
+        }
+    }
+
+    /// Get the dir that holds the build logs of a package.
+    pub fn dir(package_name: &str) -> anyhow::Result<PathBuf> {
+        Ok(crate::state::state_dir()?
+            .join("logs")
+            .join(dir_name(package_name)))
+    }
+
+    /// List the build logs of a package, newest first.
+    pub fn list(package_name: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let dir = Self::dir(package_name)?;
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut logs = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect::<Vec<_>>();
+        logs.sort();
+        logs.reverse();
+        Ok(logs)
+    }
+
+    /// Parse the time and action from the file name of a log.
+    pub fn describe(path: &Path) -> Option<(PrimitiveDateTime, String)> {
+        let file_stem = path.file_stem()?.to_str()?;
+        let (timestamp, action) = file_stem.split_at_checked(23)?;
+        let time = PrimitiveDateTime::parse(timestamp, TIMESTAMP_FORMAT).ok()?;
+        Some((time, action.trim_start_matches('-').to_string()))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+/// Show the build logs of a package.
+///
+/// With `list`, all logs are listed. Otherwise the log of the given attempt
+/// is shown, where attempt 1 is the most recent one.
+pub fn show_logs(package_name: &str, list: bool, attempt: usize) -> anyhow::Result<()> {
+    let logs = BuildLog::list(package_name)?;
+    if logs.is_empty() {
+        println!("No build logs found for package {}.", package_name.blue());
+        return Ok(());
+    }
+
+    if list {
+        for (index, path) in logs.iter().enumerate() {
+            let (time_text, action) = match BuildLog::describe(path) {
+                Some((time, action)) => (time.format(DISPLAY_FORMAT)?, action),
+                None => (String::default(), String::default()),
+            };
+            println!(
+                "{:>3}  {}  {:<9}  {}",
+                index + 1,
+                time_text.cyan(),
+                action,
+                path.display().bright_black()
+            );
+        }
+        return Ok(());
+    }
+
+    let Some(path) = attempt.checked_sub(1).and_then(|index| logs.get(index)) else {
+        anyhow::bail!(
+            "Package {} has only {} build {}.",
+            package_name,
+            logs.len(),
+            crate::helper::pluralize("log", "logs", logs.len())
+        );
+    };
+
+    // Open the log in a pager on a terminal, print it otherwise
+    if crate::helper::is_interactive() {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+        let mut pager_parts = pager.split_whitespace();
+        if let Some(program) = pager_parts.next() {
+            let status = Command::new(program).args(pager_parts).arg(path).status();
+            if status.is_ok() {
+                return Ok(());
+            }
+        }
+    }
+    print!("{}", std::fs::read_to_string(path)?);
+
+    Ok(())
+}
+
+/// Find the last relevant error in cargo's output.
+///
+/// Compiler errors are followed by summary lines like `error: could not compile`,
+/// which say little on their own. The last real error is returned, followed by
+/// the summary lines after it.
+pub fn last_error_block(log: &str) -> Option<String> {
+    let lines = log.lines().collect::<Vec<_>>();
+    let is_summary = |line: &str| {
+        line.starts_with("error: could not compile") || line.starts_with("error: failed to compile")
+    };
+
+    // Error and warning blocks start with an unindented `error` or `warning` line
+    let block_starts = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with("error") || line.starts_with("warning"))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let error_starts = block_starts
+        .iter()
+        .copied()
+        .filter(|&index| lines[index].starts_with("error"))
+        .collect::<Vec<_>>();
+    let start = error_starts
+        .iter()
+        .copied()
+        .rfind(|&index| !is_summary(lines[index]))
+        .or_else(|| error_starts.last().copied())?;
+    let end = block_starts
+        .iter()
+        .copied()
+        .find(|&index| index > start)
+        .unwrap_or(lines.len());
+
+    let mut block = lines[start..end].join("\n").trim_end().to_string();
+    for line in &lines[end..] {
+        if is_summary(line) {
+            block.push('\n');
+            block.push_str(line);
+        }
+    }
+    Some(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dir_name_keeps_crate_names() {
+        assert_eq!(dir_name("cargo-edit"), "cargo-edit");
+        assert_eq!(dir_name("tree_sitter_cli"), "tree_sitter_cli");
+    }
+
+    #[test]
+    fn dir_name_replaces_path_components() {
+        assert_eq!(dir_name("../../etc"), "______etc");
+        assert_eq!(dir_name("a/b"), "a_b");
+        assert_eq!(dir_name(".."), "__");
+        assert_eq!(dir_name(""), "_");
+    }
+
+    #[test]
+    fn last_error_block_skips_summary_lines() {
+        let log = "\
+   Compiling foo v1.0.0
+warning: unused variable: `x`
+ --> src/main.rs:2:9
+error[E0425]: cannot find value `y` in this scope
+ --> src/main.rs:3:5
+  |
+3 |     y
+  |     ^ not found in this scope
+warning: `foo` (bin \"foo\") generated 1 warning
+error: could not compile `foo` (bin \"foo\") due to 1 previous error
+error: failed to compile `foo v1.0.0`, intermediate artifacts can be found at `/tmp/target`.
+";
+        assert_eq!(
+            last_error_block(log).unwrap(),
+            "\
+error[E0425]: cannot find value `y` in this scope
+ --> src/main.rs:3:5
+  |
+3 |     y
+  |     ^ not found in this scope
+error: could not compile `foo` (bin \"foo\") due to 1 previous error
+error: failed to compile `foo v1.0.0`, intermediate artifacts can be found at `/tmp/target`."
+        );
+    }
+
+    #[test]
+    fn last_error_block_picks_the_last_error() {
+        let log = "\
+error: first
+  detail
+error: second
+  detail
+";
+        assert_eq!(last_error_block(log).unwrap(), "error: second\n  detail");
+    }
+
+    #[test]
+    fn last_error_block_falls_back_to_summary_lines() {
+        let log = "\
+   Compiling foo v1.0.0
+error: could not compile `foo`
+";
+        assert_eq!(
+            last_error_block(log).unwrap(),
+            "error: could not compile `foo`"
+        );
+    }
+
+    #[test]
+    fn last_error_block_without_errors() {
+        assert_eq!(last_error_block("warning: unused import\n"), None);
+        assert_eq!(last_error_block(""), None);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use regex::Regex;
+
+/// Tracks the progress of a cargo build from its output.
+///
+/// Cargo is asked to print its progress bar even though its output is piped
+/// (see [`BuildProgress::configure`]), which yields lines like
+/// `Building [====>    ] 45/120: serde, syn`. Until the first of those
+/// lines arrives, an indeterminate spinner is shown.
+pub struct BuildProgress {
+    bar: ProgressBar,
+    has_total: bool,
+}
+
+impl BuildProgress {
+    pub fn new(multi_progress: &MultiProgress, message: String) -> Self {
+        let bar = multi_progress.add(ProgressBar::new_spinner());
+        bar.set_style(Self::spinner_style());
+        bar.set_prefix(message);
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Self {
+            bar,
+            has_total: false,
+        }
+    }
+
+    /// Make cargo print its progress bar, even though its output is not a terminal.
+    pub fn configure(command: &mut std::process::Command) {
+        command
+            .env("CARGO_TERM_PROGRESS_WHEN", "always")
+            .env("CARGO_TERM_PROGRESS_WIDTH", "200")
+            .env("CARGO_TERM_COLOR", "never");
+    }
+
+    fn spinner_style() -> ProgressStyle {
+        ProgressStyle::with_template(
+            "{spinner:.green} {prefix} [{elapsed_precise}] {wide_msg:.dim}",
+        )
+        .unwrap()
+    }
+
+    fn bar_style() -> ProgressStyle {
+        ProgressStyle::with_template(
+            "{spinner:.green} {prefix} [{elapsed_precise}] [{bar:25.cyan/blue}] {pos}/{len} {wide_msg:.dim}",
+        )
+        .unwrap()
+        .progress_chars("=> ")
+    }
+
+    /// Update the progress from a line of cargo's stderr.
+    ///
+    /// Returns `true` if the line was a progress update, which isn't worth logging.
+    pub fn handle_line(&mut self, line: &str) -> bool {
+        static BUILDING_REGEX: OnceLock<Regex> = OnceLock::new();
+        static COMPILING_REGEX: OnceLock<Regex> = OnceLock::new();
+        let building_regex = BUILDING_REGEX.get_or_init(|| {
+            Regex::new(r"^\s*Building \[[^\]]*\]\s+(\d+)/(\d+)(?::\s*(.*))?$").unwrap()
+        });
+        let compiling_regex =
+            COMPILING_REGEX.get_or_init(|| Regex::new(r"^\s*Compiling (\S+ v\S+)").unwrap());
+
+        if let Some(captures) = building_regex.captures(line) {
+            let compiled = captures[1].parse().unwrap_or_default();
+            let total = captures[2].parse().unwrap_or_default();
+            if !self.has_total {
+                self.bar.set_style(Self::bar_style());
+                self.has_total = true;
+            }
+            self.bar.set_length(total);
+            self.bar.set_position(compiled);
+            if let Some(crates) = captures.get(3) {
+                self.bar.set_message(crates.as_str().trim().to_string());
+            }
+            return true;
+        }
+
+        if let Some(captures) = compiling_regex.captures(line) {
+            self.bar.set_message(captures[1].to_string());
+        }
+        false
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Call `f` for every line of the reader.
+///
+/// Unlike [`BufRead::lines`], this also splits on carriage returns,
+/// which cargo uses to redraw its progress bar. Empty lines are skipped.
+pub fn for_each_line(reader: impl Read, mut f: impl FnMut(&str)) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        let buffer = match reader.fill_buf() {
+            Ok([]) | Err(_) => break,
+            Ok(buffer) => buffer,
+        };
+        let length = buffer.len();
+        for &byte in buffer {
+            if byte == b'\r' || byte == b'\n' {
+                if !line.is_empty() {
+                    f(&String::from_utf8_lossy(&line));
+                    line.clear();
+                }
+            } else {
+                line.push(byte);
+            }
+        }
+        reader.consume(length);
+    }
+    if !line.is_empty() {
+        f(&String::from_utf8_lossy(&line));
+    }
+}
@@ -1,66 +1,434 @@
-use indicatif::ProgressBar;
+use indicatif::MultiProgress;
 use owo_colors::OwoColorize;
 use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::Write,
+    path::Path,
     process::{Command, Stdio},
-    time::Duration,
+    sync::{Mutex, PoisonError},
+    time::Instant,
 };
 
-fn run_with_progress(command: &mut Command, message: String) -> anyhow::Result<()> {
-    // Pipe stdout and stderr to parent
-    command.stdout(Stdio::inherit());
+use crate::{
+    build_log::{last_error_block, BuildLog},
+    build_progress::{for_each_line, BuildProgress},
+    error::CapError,
+    history::{HistoryAction, HistoryEntry, HistoryOutcome},
+    local_crates::{custom_install_root, CratesMetadataJson, GitReference, GitSource},
+    state::{CapState, Environment},
+};
+
+/// Append a line of child process output to a build log.
+fn write_line(log_file: &Mutex<&File>, line: &str) {
+    let mut log_file = log_file.lock().unwrap_or_else(PoisonError::into_inner);
+    let _ = log_file.write_all(line.as_bytes());
+    let _ = log_file.write_all(b"\n");
+}
+
+/// Run a cargo build, showing its progress and recording it in the history.
+fn run_with_progress(
+    command: &mut Command,
+    progress: &MultiProgress,
+    mut history_entry: HistoryEntry,
+    message: String,
+) -> anyhow::Result<()> {
+    let package_name = history_entry.package.clone();
+    let locked = history_entry.locked;
+    let started_at = Instant::now();
+
+    // Capture all output in a build log
+    let log = BuildLog::create(&package_name, &history_entry.action.to_string())?;
+    history_entry.log = Some(log.path().to_path_buf());
+    command.stdout(Stdio::piped());
     command.stderr(Stdio::piped());
+    BuildProgress::configure(command);
 
     // Create progress bar
-    let progress_bar = ProgressBar::new_spinner();
-    progress_bar.set_message(message);
-    progress_bar.enable_steady_tick(Duration::from_millis(100));
+    let mut build_progress = BuildProgress::new(progress, message);
 
     // Spawn command
     let mut child = command.spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
 
-    // Wait for command to finish
-    let status = child.wait()?;
+    // Drain both pipes while waiting, so a full pipe can't block cargo.
+    // Cargo reports its progress on stderr, which drives the progress bar.
+    let log_file = Mutex::new(log.file());
+    let status = std::thread::scope(|scope| {
+        if let Some(stdout) = stdout {
+            scope.spawn(|| for_each_line(stdout, |line| write_line(&log_file, line)));
+        }
+        if let Some(stderr) = stderr {
+            scope.spawn(|| {
+                for_each_line(stderr, |line| {
+                    if !build_progress.handle_line(line) {
+                        write_line(&log_file, line);
+                    }
+                })
+            });
+        }
+        child.wait()
+    })?;
 
     // Clear progress bar
-    progress_bar.finish_and_clear();
+    build_progress.finish();
+
+    history_entry.duration = Some(started_at.elapsed().as_secs_f64());
+    let outcome = if status.success() {
+        HistoryOutcome::Success
+    } else {
+        HistoryOutcome::Failed
+    };
+    if let Err(err) = history_entry.append(outcome) {
+        progress.suspend(|| eprintln!("Unable to record history: {:#}", err));
+    }
 
     // Check for errors
     if !status.success() {
-        anyhow::bail!("Failed to install package.");
+        let log_str = std::fs::read_to_string(log.path()).unwrap_or_default();
+        progress.suspend(|| {
+            if let Some(error_block) = last_error_block(&log_str) {
+                eprintln!("{}", error_block);
+            }
+            eprintln!("Full build log: {}", log.path().display());
+        });
+        return Err(CapError::BuildFailed(package_name).into());
     }
 
+    // Cargo doesn't record `--locked`, so cap remembers it for updates.
+    // The build succeeded, so failing to remember it is no reason to fail.
+    let result = crate::path_source::source_hash_key(&package_name).and_then(|locked_key| {
+        CapState::modify(|state| {
+            if locked {
+                state.locked_packages.insert(locked_key);
+            } else {
+                state.locked_packages.remove(&locked_key);
+            }
+        })
+    });
+    if let Err(err) = result {
+        progress.suspend(|| {
+            eprintln!(
+                "Unable to remember whether {} was built with --locked: {:#}",
+                package_name, err
+            )
+        });
+    }
     Ok(())
 }
 
+/// Features and toolchain to build a package with.
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    pub features: BTreeSet<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// Toolchain to build with. Defaults to the one of the active environment.
+    pub toolchain: Option<String>,
+    pub locked: bool,
+}
+
+impl BuildOptions {
+    /// Build options that reproduce the current install of a package.
+    ///
+    /// Features and toolchain are taken from `.crates2.json`. If cargo didn't
+    /// record the package there, the defaults are used. Whether the package
+    /// was built with `--locked` is taken from the cap state.
+    pub fn recorded(package_name: &str) -> Self {
+        let locked = crate::path_source::source_hash_key(package_name).is_ok_and(|locked_key| {
+            CapState::load().is_ok_and(|state| state.locked_packages.contains(&locked_key))
+        });
+        let Some(record) = CratesMetadataJson::load()
+            .ok()
+            .and_then(|crates_json| crates_json.get(package_name).cloned())
+        else {
+            return Self {
+                locked,
+                ..Default::default()
+            };
+        };
+        Self {
+            features: record.features,
+            all_features: record.all_features,
+            no_default_features: record.no_default_features,
+            toolchain: record
+                .rustc
+                .as_ref()
+                .is_some_and(|rustc| rustc.contains("-nightly"))
+                .then(|| "nightly".to_string()),
+            locked,
+        }
+    }
+
+    /// Get the toolchain to build with, if it isn't the default one.
+    fn toolchain(&self) -> anyhow::Result<Option<String>> {
+        Ok(match &self.toolchain {
+            Some(toolchain) => Some(toolchain.clone()),
+            None => Environment::active()?.and_then(|env| env.manifest().toolchain.clone()),
+        })
+    }
+
+    /// Create a cargo command, selecting the toolchain if needed.
+    fn cargo(&self) -> anyhow::Result<Command> {
+        let mut cargo = Command::new("cargo");
+        if let Some(toolchain) = self.toolchain()? {
+            cargo.arg(format!("+{}", toolchain));
+        }
+        Ok(cargo)
+    }
+
+    /// Start a history entry for a build with these options.
+    fn history_entry(
+        &self,
+        action: HistoryAction,
+        package_name: &str,
+    ) -> anyhow::Result<HistoryEntry> {
+        let mut entry = HistoryEntry::new(action, package_name);
+        entry.toolchain = self.toolchain()?;
+        entry.features = self.features.iter().cloned().collect();
+        entry.all_features = self.all_features;
+        entry.no_default_features = self.no_default_features;
+        entry.locked = self.locked;
+        Ok(entry)
+    }
+
+    /// Add the feature and lockfile flags to an install command.
+    fn apply(&self, cargo: &mut Command) {
+        if self.locked {
+            cargo.arg("--locked");
+        }
+        if self.all_features {
+            cargo.arg("--all-features");
+        }
+        if self.no_default_features {
+            cargo.arg("--no-default-features");
+        }
+        if !self.features.is_empty() {
+            cargo
+                .arg("--features")
+                .arg(self.features.iter().cloned().collect::<Vec<_>>().join(","));
+        }
+    }
+}
+
+/// Point cargo at the install root, if it isn't the cargo home dir.
+fn apply_root(cargo: &mut Command) -> anyhow::Result<()> {
+    if let Some(root) = custom_install_root()? {
+        cargo.arg("--root").arg(root);
+    }
+    Ok(())
+}
+
+/// Format a command the way it would be typed into a shell.
+fn format_command(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| {
+            let part = part.to_string_lossy();
+            let is_plain = !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.,=+/:@".contains(c));
+            if is_plain {
+                part.to_string()
+            } else {
+                format!("'{}'", part.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Print a command instead of running it.
+fn print_command(command: &Command) {
+    println!("{} {}", "Would run:".cyan(), format_command(command));
+}
+
+/// Install a package from crates.io.
+///
+/// Cargo picks the latest version itself, unless the version is `pinned`.
 pub fn install_package(
     package_name: impl AsRef<str>,
     version: semver::Version,
-    locked: bool,
+    pinned: bool,
     forced: bool,
-    nightly: bool,
+    options: &BuildOptions,
+    progress: &MultiProgress,
+    dry_run: bool,
 ) -> anyhow::Result<()> {
     let package_name = package_name.as_ref();
 
-    let mut cargo = {
-        let mut cargo = Command::new("cargo");
-        if nightly {
-            cargo.arg("+nightly");
+    let mut cargo = options.cargo()?;
+    cargo.arg("install");
+    if forced {
+        cargo.arg("--force");
+    }
+    if pinned {
+        cargo.arg("--version").arg(format!("={}", version));
+    }
+    options.apply(&mut cargo);
+    apply_root(&mut cargo)?;
+    cargo.arg(package_name);
+
+    if dry_run {
+        print_command(&cargo);
+        return Ok(());
+    }
+
+    let mut history_entry = options.history_entry(HistoryAction::Install, package_name)?;
+    history_entry.to = Some(version.to_string());
+    run_with_progress(
+        &mut cargo,
+        progress,
+        history_entry,
+        format!(
+            "Installing package {} {}...",
+            package_name.blue(),
+            version.bright_black()
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Install a package from a git repository.
+///
+/// The `commit` that the reference is expected to resolve to is recorded in the history,
+/// along with the commit of the source if it is already installed.
+pub fn install_git_package(
+    package_name: impl AsRef<str>,
+    source: &GitSource,
+    commit: Option<&str>,
+    forced: bool,
+    options: &BuildOptions,
+    progress: &MultiProgress,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let package_name = package_name.as_ref();
+
+    let mut cargo = options.cargo()?;
+    cargo.arg("install");
+    if forced {
+        cargo.arg("--force");
+    }
+    cargo.arg("--git").arg(&source.url);
+    match &source.reference {
+        GitReference::DefaultBranch => (),
+        GitReference::Branch(branch) => {
+            cargo.arg("--branch").arg(branch);
         }
-        cargo.arg("install");
-        if forced {
-            cargo.arg("--force");
+        GitReference::Tag(tag) => {
+            cargo.arg("--tag").arg(tag);
         }
-        if locked {
-            cargo.arg("--locked");
+        GitReference::Rev(rev) => {
+            cargo.arg("--rev").arg(rev);
         }
-        cargo.arg(package_name);
-        cargo
-    };
+    }
+    options.apply(&mut cargo);
+    apply_root(&mut cargo)?;
+    cargo.arg(package_name);
+
+    if dry_run {
+        print_command(&cargo);
+        return Ok(());
+    }
 
+    let mut history_entry = options.history_entry(HistoryAction::Install, package_name)?;
+    history_entry.from = source.short_commit().map(str::to_string);
+    history_entry.to = commit.map(|commit| commit.get(..7).unwrap_or(commit).to_string());
     run_with_progress(
         &mut cargo,
+        progress,
+        history_entry,
         format!(
-            "Installing package {} {}...",
+            "Installing package {} from {} ({})...",
+            package_name.blue(),
+            source.url.bright_black(),
+            source.reference.bright_black()
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Install a package from a local source dir.
+pub fn install_path_package(
+    package_name: impl AsRef<str>,
+    source_dir: &Path,
+    forced: bool,
+    options: &BuildOptions,
+    progress: &MultiProgress,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let package_name = package_name.as_ref();
+
+    let mut cargo = options.cargo()?;
+    cargo.arg("install");
+    if forced {
+        cargo.arg("--force");
+    }
+    cargo.arg("--path").arg(source_dir);
+    options.apply(&mut cargo);
+    apply_root(&mut cargo)?;
+
+    if dry_run {
+        print_command(&cargo);
+        return Ok(());
+    }
+
+    let mut history_entry = options.history_entry(HistoryAction::Install, package_name)?;
+    history_entry.to = crate::path_source::manifest_version(source_dir)
+        .ok()
+        .flatten()
+        .map(|version| version.to_string());
+    run_with_progress(
+        &mut cargo,
+        progress,
+        history_entry,
+        format!(
+            "Installing package {} from {}...",
+            package_name.blue(),
+            source_dir.display().bright_black()
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Reinstall a package at exactly the given version.
+pub fn reinstall_package(
+    package_name: impl AsRef<str>,
+    version: semver::Version,
+    options: &BuildOptions,
+    progress: &MultiProgress,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let package_name = package_name.as_ref();
+
+    let mut cargo = options.cargo()?;
+    cargo
+        .arg("install")
+        .arg("--force")
+        .arg("--version")
+        .arg(format!("={}", version));
+    options.apply(&mut cargo);
+    apply_root(&mut cargo)?;
+    cargo.arg(package_name);
+
+    if dry_run {
+        print_command(&cargo);
+        return Ok(());
+    }
+
+    let mut history_entry = options.history_entry(HistoryAction::Reinstall, package_name)?;
+    history_entry.to = Some(version.to_string());
+    run_with_progress(
+        &mut cargo,
+        progress,
+        history_entry,
+        format!(
+            "Reinstalling package {} {}...",
             package_name.blue(),
             version.bright_black()
         ),
@@ -69,14 +437,31 @@ pub fn install_package(
     Ok(())
 }
 
-pub fn uninstall_package(package_name: impl AsRef<str>) -> anyhow::Result<()> {
+/// Uninstall a package.
+///
+/// If `binaries` is empty, the whole package is removed.
+/// Otherwise only the given binaries are removed.
+pub fn uninstall_package(
+    package_name: impl AsRef<str>,
+    binaries: &[&str],
+    dry_run: bool,
+) -> anyhow::Result<()> {
     let package_name = package_name.as_ref();
 
-    let output = Command::new("cargo")
-        .arg("uninstall")
-        .arg(package_name)
-        .spawn()?
-        .wait_with_output()?;
+    let mut cargo = Command::new("cargo");
+    cargo.arg("uninstall");
+    apply_root(&mut cargo)?;
+    for binary in binaries {
+        cargo.arg("--bin").arg(binary);
+    }
+    cargo.arg(package_name);
+
+    if dry_run {
+        print_command(&cargo);
+        return Ok(());
+    }
+
+    let output = cargo.spawn()?.wait_with_output()?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -90,19 +475,34 @@ pub fn update_package(
     package_name: impl AsRef<str>,
     local_version: semver::Version,
     target_version: semver::Version,
+    options: &BuildOptions,
+    progress: &MultiProgress,
+    dry_run: bool,
 ) -> anyhow::Result<()> {
     let package_name = package_name.as_ref();
 
-    let mut cargo = Command::new("cargo");
+    let mut cargo = options.cargo()?;
     cargo
         .arg("install")
         .arg("--force")
         .arg("--version")
-        .arg(target_version.to_string())
-        .arg(package_name);
+        .arg(target_version.to_string());
+    options.apply(&mut cargo);
+    apply_root(&mut cargo)?;
+    cargo.arg(package_name);
+
+    if dry_run {
+        print_command(&cargo);
+        return Ok(());
+    }
 
+    let mut history_entry = options.history_entry(HistoryAction::Update, package_name)?;
+    history_entry.from = Some(local_version.to_string());
+    history_entry.to = Some(target_version.to_string());
     run_with_progress(
         &mut cargo,
+        progress,
+        history_entry,
         format!(
             "{} package {} from {} to {}...",
             "Updating".green(),
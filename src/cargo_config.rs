@@ -0,0 +1,107 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// A single cargo config file.
+#[derive(Debug)]
+struct ConfigFile {
+    path: PathBuf,
+    table: toml::Table,
+}
+
+/// Cargo's configuration, read the same way cargo reads it.
+///
+/// Config files are searched in the current directory and all of its
+/// parents, and finally in the cargo home dir. Files closer to the current
+/// directory take precedence.
+#[derive(Debug, Default)]
+pub struct CargoConfig {
+    files: Vec<ConfigFile>,
+}
+
+impl CargoConfig {
+    pub fn load() -> anyhow::Result<Self> {
+        let mut dirs = Vec::new();
+        if let Ok(current_dir) = std::env::current_dir() {
+            dirs.extend(current_dir.ancestors().map(|dir| dir.join(".cargo")));
+        }
+        if let Ok(cargo_home_dir) = home::cargo_home() {
+            if !dirs.contains(&cargo_home_dir) {
+                dirs.push(cargo_home_dir);
+            }
+        }
+
+        let mut files = Vec::new();
+        for dir in dirs {
+            // Cargo prefers `config.toml`, but still reads the legacy `config` file.
+            let Some(path) = ["config.toml", "config"]
+                .into_iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+            else {
+                continue;
+            };
+            let config_str = read_to_string(&path)
+                .with_context(|| format!("Unable to read cargo config {}", path.display()))?;
+            let table = toml::from_str(&config_str)
+                .with_context(|| format!("Unable to parse cargo config {}", path.display()))?;
+            files.push(ConfigFile { path, table });
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Get a value by its dotted key, along with the path of the file that set it.
+    pub fn get(&self, key: &str) -> Option<(&toml::Value, &Path)> {
+        self.files.iter().find_map(|file| {
+            let mut parts = key.split('.');
+            let mut value = file.table.get(parts.next()?)?;
+            for part in parts {
+                value = value.get(part)?;
+            }
+            Some((value, file.path.as_path()))
+        })
+    }
+
+    /// Get a string value by its dotted key.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|(value, _)| value.as_str())
+    }
+
+    /// Get a path value by its dotted key.
+    ///
+    /// Relative paths are resolved against the parent of the dir that holds
+    /// the config file, so `/project/.cargo/config.toml` resolves them
+    /// against `/project`.
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        let (value, config_path) = self.get(key)?;
+        let path = Path::new(value.as_str()?);
+        if path.is_absolute() {
+            return Some(path.to_path_buf());
+        }
+        let base_dir = config_path.parent()?.parent()?;
+        Some(base_dir.join(path))
+    }
+
+    /// Get the index URL to use for crates.io, following `[source]` replacements.
+    pub fn crates_io_index_url(&self) -> Option<String> {
+        let mut source_name = "crates-io".to_string();
+        // Guard against replacement cycles
+        for _ in 0..16 {
+            match self.get_str(&format!("source.{}.replace-with", source_name)) {
+                Some(replacement) => source_name = replacement.to_string(),
+                None => break,
+            }
+        }
+        if source_name == "crates-io" {
+            return None;
+        }
+        // The replacement is either a source or a registry from `[registries]`
+        self.get_str(&format!("source.{}.registry", source_name))
+            .or_else(|| self.get_str(&format!("registries.{}.index", source_name)))
+            .map(str::to_string)
+    }
+}
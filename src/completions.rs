@@ -0,0 +1,131 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use crate::{CratesRegistry, PackageTree};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The kind of package names completed for an argument.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletionNames {
+    /// Names of installed packages.
+    Installed,
+    /// Names of crates in the local crates.io index.
+    Registry,
+}
+
+/// Completes package names of uninstall, update and check with installed packages,
+/// and those of install and info with crates from the index.
+///
+/// Options that take a value are left to the generated completions.
+/// The subcommand is the first word that isn't an option or the value of `--root`.
+const BASH_DYNAMIC: &str = r#"
+_cap_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" kind="" subcommand="" i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            --root) ((i++)) ;;
+            -*) ;;
+            *) subcommand="${COMP_WORDS[i]}"; break ;;
+        esac
+    done
+    case "$subcommand" in
+        uninstall|update|check) kind=installed ;;
+        install|i|info) kind=registry ;;
+    esac
+    case "$prev" in
+        --root|--git|--branch|--tag|--rev|--path|-j|--jobs) kind="" ;;
+    esac
+    if [[ -n "$kind" && "$cur" != -* ]]; then
+        COMPREPLY=($(compgen -W "$(cap __complete "$kind" "$cur" 2>/dev/null)" -- "$cur"))
+        return 0
+    fi
+    _cap "$@"
+}
+complete -F _cap_dynamic -o bashdefault -o default cap
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_cap_dynamic() {
+    local kind="" subcommand="" i
+    for ((i = 2; i < CURRENT; i++)); do
+        case "$words[i]" in
+            --root) ((i++)) ;;
+            -*) ;;
+            *) subcommand="$words[i]"; break ;;
+        esac
+    done
+    case "$subcommand" in
+        uninstall|update|check) kind=installed ;;
+        install|i|info) kind=registry ;;
+    esac
+    case "$words[CURRENT-1]" in
+        --root|--git|--branch|--tag|--rev|--path|-j|--jobs) kind="" ;;
+    esac
+    if [[ -n "$kind" && "$PREFIX" != -* ]]; then
+        local -a names
+        names=(${(f)"$(cap __complete $kind $PREFIX 2>/dev/null)"})
+        compadd -a names
+        return
+    fi
+    _cap "$@"
+}
+compdef _cap_dynamic cap
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c cap -n "__fish_seen_subcommand_from uninstall update check" -f -a "(cap __complete installed (commandline -ct) 2>/dev/null)"
+complete -c cap -n "__fish_seen_subcommand_from install i info" -f -a "(cap __complete registry (commandline -ct) 2>/dev/null)"
+"#;
+
+/// Print the completion script for a shell.
+///
+/// The script generated from the command definitions is extended with
+/// completion of package names, which calls back into `cap __complete`.
+pub fn print_completions(
+    shell: CompletionShell,
+    command: &mut clap::Command,
+) -> anyhow::Result<()> {
+    let (generator, dynamic) = match shell {
+        CompletionShell::Bash => (clap_complete::Shell::Bash, BASH_DYNAMIC),
+        CompletionShell::Zsh => (clap_complete::Shell::Zsh, ZSH_DYNAMIC),
+        CompletionShell::Fish => (clap_complete::Shell::Fish, FISH_DYNAMIC),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    clap_complete::generate(generator, command, "cap", &mut stdout);
+    stdout.write_all(dynamic.as_bytes())?;
+    Ok(())
+}
+
+/// Print the package names that start with a prefix, one per line.
+///
+/// Registry names are only listed once part of the name is typed,
+/// as there are far too many of them to be useful otherwise,
+/// and only from an index that was already fetched.
+pub fn print_names(names: CompletionNames, prefix: &str) -> anyhow::Result<()> {
+    let names = match names {
+        CompletionNames::Installed => PackageTree::build()?
+            .packages()
+            .map(|package| package.name().to_string())
+            .filter(|name| name.starts_with(prefix))
+            .collect(),
+        CompletionNames::Registry if prefix.is_empty() => Vec::new(),
+        // Completion must not wait for the index to be cloned
+        CompletionNames::Registry => CratesRegistry::open_existing()?
+            .map(|registry| registry.crate_names(prefix))
+            .unwrap_or_default(),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    for name in names {
+        writeln!(stdout, "{}", name)?;
+    }
+    Ok(())
+}
@@ -1,5 +1,8 @@
 mod crates_io;
 mod crates_registry;
+mod dependency_resolver;
+mod target_cfg;
 
-pub use self::crates_io::CratesIoClient;
+pub use self::crates_io::{date_text, CratesIoClient};
 pub use crates_registry::CratesRegistry;
+pub use dependency_resolver::DependencyResolver;
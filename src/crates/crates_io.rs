@@ -1,29 +1,278 @@
-use curl::easy::Easy;
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use curl::easy::List;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime};
+
+use super::CratesRegistry;
+use crate::{
+    error::CapError,
+    state::{state_dir, CapConfig},
+};
+
+/// The crate metadata of an API response.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrateMetadata {
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    pub downloads: u64,
+    pub recent_downloads: Option<u64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A published version of a crate.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrateVersion {
+    pub num: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub yanked: bool,
+    pub license: Option<String>,
+}
+
+/// The response of `/api/v1/crates/{name}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrateInfo {
+    #[serde(rename = "crate")]
+    pub metadata: CrateMetadata,
+    /// All versions, newest first.
+    pub versions: Vec<CrateVersion>,
+}
+
+impl CrateInfo {
+    pub fn version(&self, version: &str) -> Option<&CrateVersion> {
+        self.versions.iter().find(|entry| entry.num == version)
+    }
+}
+
+/// Format an API timestamp as a date, like `2023-11-28`.
+pub fn date_text(timestamp: &str) -> String {
+    OffsetDateTime::parse(timestamp, &Rfc3339)
+        .ok()
+        .and_then(|time| {
+            time.format(format_description!("[year]-[month]-[day]"))
+                .ok()
+        })
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// A cached API response, revalidated with its ETag.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    etag: Option<String>,
+    body: String,
+}
+
+/// Turn a base URL or crate name into a file name, like `crates.io` or `127.0.0.1_8080`.
+fn cache_file_name(name: &str) -> String {
+    let name = name.split_once("://").map_or(name, |(_, rest)| rest);
+    name.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+impl CachedResponse {
+    fn path(cache_dir: &Path, crate_name: &str) -> PathBuf {
+        cache_dir.join(format!("{}.json", cache_file_name(crate_name)))
+    }
+
+    fn load(cache_dir: &Path, crate_name: &str) -> Option<Self> {
+        let cache_str = read_to_string(Self::path(cache_dir, crate_name)).ok()?;
+        serde_json::from_str(&cache_str).ok()
+    }
+
+    fn save(&self, cache_dir: &Path, crate_name: &str) -> anyhow::Result<()> {
+        std::fs::create_dir_all(cache_dir).context("Unable to create API cache dir.")?;
+        std::fs::write(
+            Self::path(cache_dir, crate_name),
+            serde_json::to_string(self)?,
+        )
+        .context("Unable to write API cache.")
+    }
+}
 
 pub struct CratesIoClient {
-    registry: crates_io::Registry,
+    base_url: String,
+    /// Where responses are cached, separately for each base URL.
+    cache_dir: PathBuf,
 }
 
 impl CratesIoClient {
-    pub fn new() -> Self {
-        let index_url = "https://crates.io".to_string();
-        let mut handle = Easy::new();
-        handle
-            .useragent("cap package manager (github.com/splittydev/cap)")
-            .unwrap();
+    /// Create a client for the crates.io API.
+    ///
+    /// The base URL is `api.url` from the cap config if set. Otherwise, if cargo
+    /// replaces crates.io with a mirror, the API of the mirror is used.
+    pub fn new() -> anyhow::Result<Self> {
+        let base_url = match CapConfig::load()?.api.url {
+            Some(url) => url,
+            None => {
+                CratesRegistry::mirror_api_url()?.unwrap_or_else(|| "https://crates.io".to_string())
+            }
+        };
+        let base_url = base_url.trim_end_matches('/');
+        let cache_dir = state_dir()?
+            .join("api_cache")
+            .join(cache_file_name(base_url));
+        Ok(Self::with_cache_dir(base_url, cache_dir))
+    }
+
+    fn with_cache_dir(base_url: &str, cache_dir: PathBuf) -> Self {
         Self {
-            registry: crates_io::Registry::new_handle(index_url, None, handle, false),
+            base_url: base_url.to_string(),
+            cache_dir,
         }
     }
 
-    pub fn get_package_info(
-        &mut self,
-        package_name: impl AsRef<str>,
-    ) -> anyhow::Result<crates_io::Crate> {
-        let (crates, _) = self.registry.search(package_name.as_ref(), 1)?;
-        let Some(crate_) = crates.into_iter().next() else {
-            anyhow::bail!("Failed to find package on crates.io: {}", package_name.as_ref());
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Look up a crate by its exact name.
+    ///
+    /// Responses are cached on disk and revalidated with their ETag,
+    /// so unchanged crates aren't downloaded again.
+    pub fn get_package_info(&mut self, package_name: impl AsRef<str>) -> anyhow::Result<CrateInfo> {
+        let package_name = package_name.as_ref();
+        let cached = CachedResponse::load(&self.cache_dir, package_name);
+        let url = format!("{}/api/v1/crates/{}", self.base_url, package_name);
+
+        let mut handle = crate::http::handle()?;
+        handle.url(&url)?;
+        handle.follow_location(true)?;
+        let mut headers = List::new();
+        headers.append("Accept: application/json")?;
+        if let Some(etag) = cached.as_ref().and_then(|cached| cached.etag.as_ref()) {
+            headers.append(&format!("If-None-Match: {}", etag))?;
+        }
+        handle.http_headers(headers)?;
+
+        let mut body = Vec::new();
+        let mut etag = None;
+        {
+            let mut transfer = handle.transfer();
+            transfer.header_function(|header| {
+                let header = String::from_utf8_lossy(header);
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.trim().eq_ignore_ascii_case("etag") {
+                        etag = Some(value.trim().to_string());
+                    }
+                }
+                true
+            })?;
+            transfer.write_function(|data| {
+                body.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            transfer.perform().context(CapError::Network)?;
+        }
+
+        let response = match (handle.response_code()?, cached) {
+            (304, Some(cached)) => cached,
+            (200, _) => {
+                let response = CachedResponse {
+                    etag,
+                    body: String::from_utf8(body).context("API response is not valid UTF-8.")?,
+                };
+                if let Err(err) = response.save(&self.cache_dir, package_name) {
+                    log::warn!("{:#}", err);
+                }
+                response
+            }
+            (404, _) => return Err(CapError::NotFound(package_name.to_string()).into()),
+            (code, _) => {
+                return Err(anyhow::anyhow!("{} returned HTTP status {}.", url, code)
+                    .context(CapError::Network))
+            }
         };
-        Ok(crate_)
+        serde_json::from_str(&response.body)
+            .with_context(|| format!("Unable to parse API response for {}.", package_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{response, serve};
+
+    const CRATE_JSON: &str = r#"{
+        "crate": {"downloads": 42, "created_at": "2023-01-01T00:00:00Z", "updated_at": "2023-11-28T00:00:00Z"},
+        "versions": [{"num": "1.0.0", "created_at": "2023-11-28T00:00:00Z", "license": "MIT"}]
+    }"#;
+
+    #[test]
+    fn cache_file_names() {
+        assert_eq!(cache_file_name("https://crates.io"), "crates.io");
+        assert_eq!(cache_file_name("http://127.0.0.1:8080"), "127.0.0.1_8080");
+        assert_eq!(
+            cache_file_name("https://mirror.example.com/crates"),
+            "mirror.example.com_crates"
+        );
+        assert_eq!(cache_file_name("Serde"), "serde");
+        assert_eq!(cache_file_name("../escape"), ".._escape");
+    }
+
+    #[test]
+    fn responses_are_revalidated_with_their_etag() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (base_url, server) = serve(vec![
+            response("200 OK", "ETag: \"v1\"\r\n", CRATE_JSON),
+            response("304 Not Modified", "ETag: \"v1\"\r\n", ""),
+        ]);
+        let mut client = CratesIoClient::with_cache_dir(&base_url, cache_dir.path().to_path_buf());
+
+        let info = client.get_package_info("foo").unwrap();
+        assert_eq!(info.metadata.downloads, 42);
+        assert!(cache_dir.path().join("foo.json").exists());
+
+        let info = client.get_package_info("foo").unwrap();
+        assert_eq!(
+            info.version("1.0.0").unwrap().license.as_deref(),
+            Some("MIT")
+        );
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /api/v1/crates/foo "));
+        assert!(!requests[0].contains("If-None-Match"));
+        assert!(requests[1].contains("If-None-Match: \"v1\""));
+    }
+
+    #[test]
+    fn missing_crates_are_not_found() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (base_url, server) = serve(vec![response("404 Not Found", "", "")]);
+        let mut client = CratesIoClient::with_cache_dir(&base_url, cache_dir.path().to_path_buf());
+
+        let err = client.get_package_info("missing").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CapError>(),
+            Some(CapError::NotFound(name)) if name == "missing"
+        ));
+        assert!(!cache_dir.path().join("missing.json").exists());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn server_errors_are_network_errors() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (base_url, server) = serve(vec![response("503 Service Unavailable", "", "")]);
+        let mut client = CratesIoClient::with_cache_dir(&base_url, cache_dir.path().to_path_buf());
+
+        let err = client.get_package_info("foo").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CapError>(),
+            Some(CapError::Network)
+        ));
+        server.join().unwrap();
     }
 }
@@ -1,12 +1,19 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Context;
-use crates_index::GitIndex;
-use home::home_dir;
+use crates_index::{local_path_and_canonical_url, GitIndex};
+use owo_colors::OwoColorize;
 use rayon::prelude::ParallelIterator;
 use regex::Regex;
 
-use crate::Package;
+use crate::{
+    cargo_config::CargoConfig, error::CapError, helper, local_crates::PackageSource, Package,
+};
 
 // const CRATES_IO_SPARSE_INDEX_URL: &str = "sparse+https://index.crates.io/";
 
@@ -15,20 +22,211 @@ pub struct CratesRegistry {
 }
 
 impl CratesRegistry {
-    pub fn new() -> Self {
-        let index = Self::get_git_index().unwrap();
+    pub fn new() -> anyhow::Result<Self> {
+        let index = Self::get_git_index()?;
 
-        Self { index }
+        Ok(Self { index })
     }
 
-    fn get_git_index() -> Result<GitIndex, crates_index::Error> {
-        let Ok(index) = GitIndex::new_cargo_default() else {
-            eprintln!("Failed to get crates.io index, recloning...");
-            let index_dir = home_dir().unwrap().join(".cargo/registry/index");
-            std::fs::remove_dir_all(index_dir).unwrap();
-            return GitIndex::new_cargo_default()
+    /// Open the index only if it was fetched before, without cloning it.
+    pub fn open_existing() -> anyhow::Result<Option<Self>> {
+        let index = GitIndex::try_from_url(&Self::git_index_url()?)?;
+        Ok(index.map(|index| Self { index }))
+    }
+
+    fn get_git_index() -> anyhow::Result<GitIndex> {
+        let index_url = Self::git_index_url()?;
+        let err = match GitIndex::from_url(&index_url) {
+            Ok(index) => return Ok(index),
+            Err(err) => err,
+        };
+
+        // A missing index that can't be cloned is a network problem, not a broken index.
+        let index_dir = Self::local_index_dir(&index_url)?;
+        if !index_dir.exists() {
+            return Err(err)
+                .context(CapError::Network)
+                .context("Failed to fetch crates.io index");
+        }
+
+        eprintln!(
+            "Failed to open crates.io index at {}: {}",
+            index_dir.display(),
+            err
+        );
+        if !helper::is_interactive()
+            || !helper::confirm("Move the broken index aside and fetch it again?")?
+        {
+            return Err(CapError::IndexUnavailable).context(
+                "The crates.io index is broken. Run `cap index repair` to fetch it again.",
+            );
+        }
+
+        Self::move_index_aside(&index_dir)?;
+        GitIndex::from_url(&index_url)
+            .context(CapError::Network)
+            .context("Failed to fetch crates.io index")
+    }
+
+    /// Get the URL of the crates.io index, honoring cargo's source replacement.
+    ///
+    /// A replacement may be a sparse registry, whose URL starts with `sparse+`.
+    pub fn index_url() -> anyhow::Result<String> {
+        let config = CargoConfig::load()?;
+        Ok(config
+            .crates_io_index_url()
+            .unwrap_or_else(|| crates_index::git::URL.to_string()))
+    }
+
+    /// Get the URL of the crates.io index, if it is a git index.
+    ///
+    /// cap can only read git indexes. A sparse replacement is refused, so its
+    /// cache is never mistaken for a broken git index.
+    fn git_index_url() -> anyhow::Result<String> {
+        let index_url = Self::index_url()?;
+        if index_url.starts_with("sparse+") {
+            return Err(CapError::UnsupportedSource(index_url)).context(
+                "crates.io is replaced by a sparse registry, but cap can only read git indexes",
+            );
+        }
+        Ok(index_url)
+    }
+
+    /// Get the API URL of the registry that replaces crates.io, if any.
+    ///
+    /// Mirrors announce their API in the `config.json` of their index.
+    /// Mirrors without an API, or whose index wasn't fetched yet, have none.
+    /// The URL is looked up once per process, as sparse mirrors need a download.
+    pub fn mirror_api_url() -> anyhow::Result<Option<String>> {
+        static API_URL: OnceLock<Option<String>> = OnceLock::new();
+        if let Some(api_url) = API_URL.get() {
+            return Ok(api_url.clone());
+        }
+        let api_url = Self::find_mirror_api_url()?;
+        Ok(API_URL.get_or_init(|| api_url).clone())
+    }
+
+    fn find_mirror_api_url() -> anyhow::Result<Option<String>> {
+        let Some(index_url) = CargoConfig::load()?.crates_io_index_url() else {
+            return Ok(None);
+        };
+        if let Some(index_url) = index_url.strip_prefix("sparse+") {
+            return Ok(Self::sparse_index_config(index_url)
+                .ok()
+                .and_then(|config| config.api));
+        }
+        let Ok(Some(index)) = GitIndex::try_from_url(&index_url) else {
+            return Ok(None);
         };
-        Ok(index)
+        Ok(index.index_config().ok().and_then(|config| config.api))
+    }
+
+    /// Download the `config.json` of a sparse index, given its URL without the `sparse+` prefix.
+    fn sparse_index_config(index_url: &str) -> anyhow::Result<crates_index::IndexConfig> {
+        let url = format!("{}/config.json", index_url.trim_end_matches('/'));
+        let mut handle = crate::http::handle()?;
+        handle.url(&url)?;
+        handle.follow_location(true)?;
+        let mut body = Vec::new();
+        {
+            let mut transfer = handle.transfer();
+            transfer.write_function(|data| {
+                body.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            transfer.perform().context(CapError::Network)?;
+        }
+        match handle.response_code()? {
+            200 => {
+                serde_json::from_slice(&body).with_context(|| format!("Unable to parse {}.", url))
+            }
+            code => Err(anyhow::anyhow!("{} returned HTTP status {}.", url, code)
+                .context(CapError::Network)),
+        }
+    }
+
+    /// Get the directory cargo keeps a git index in.
+    fn local_index_dir(index_url: &str) -> anyhow::Result<PathBuf> {
+        let (index_dir, _) = local_path_and_canonical_url(index_url, None)
+            .context("Unable to find crates.io index dir")?;
+        Ok(index_dir)
+    }
+
+    /// Move a broken index dir aside, so it can be fetched again.
+    ///
+    /// Only the crates.io index dir is touched, the indexes of other registries are kept.
+    /// The broken dir is kept next to it with a `.broken-<timestamp>` suffix.
+    fn move_index_aside(index_dir: &Path) -> anyhow::Result<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let file_name = index_dir
+            .file_name()
+            .context("Invalid crates.io index dir")?
+            .to_string_lossy();
+        let backup_dir = index_dir.with_file_name(format!("{}.broken-{}", file_name, timestamp));
+
+        std::fs::rename(index_dir, &backup_dir).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                index_dir.display(),
+                backup_dir.display()
+            )
+        })?;
+        eprintln!("Moved broken crates.io index to {}.", backup_dir.display());
+
+        Ok(backup_dir)
+    }
+
+    /// Repair the crates.io index by moving it aside and fetching it again.
+    ///
+    /// Unless `forced` is set, a healthy index is left alone.
+    pub fn repair_index(forced: bool, assume_yes: bool) -> anyhow::Result<()> {
+        let index_url = Self::git_index_url()?;
+        let index_dir = Self::local_index_dir(&index_url)?;
+
+        if index_dir.exists() {
+            let is_healthy = matches!(
+                GitIndex::try_from_url(&index_url),
+                Ok(Some(index)) if index.crate_("serde").is_some()
+            );
+            if is_healthy && !forced {
+                println!(
+                    "The crates.io index at {} is {}.",
+                    index_dir.display(),
+                    "healthy".green()
+                );
+                return Ok(());
+            }
+
+            if !assume_yes {
+                if !helper::is_interactive() {
+                    anyhow::bail!(
+                        "Refusing to move the index without confirmation. Pass --yes to proceed."
+                    );
+                }
+                let prompt = format!("Move {} aside and fetch it again?", index_dir.display());
+                if !helper::confirm(prompt)? {
+                    println!("Aborted.");
+                    return Ok(());
+                }
+            }
+
+            Self::move_index_aside(&index_dir)?;
+        }
+
+        let progress_bar = indicatif::ProgressBar::new_spinner();
+        progress_bar.set_message("Fetching crates.io index...");
+        progress_bar.enable_steady_tick(Duration::from_millis(100));
+        let index = GitIndex::from_url(&index_url);
+        progress_bar.finish_and_clear();
+        index
+            .context(CapError::Network)
+            .context("Failed to fetch crates.io index")?;
+
+        println!("{} the crates.io index.", "Repaired".green());
+        Ok(())
     }
 
     /// Update the crates.io index.
@@ -37,9 +235,20 @@ impl CratesRegistry {
     pub fn _update_index(&mut self) -> anyhow::Result<()> {
         self.index
             .update()
+            .context(CapError::Network)
             .context("Failed to update crates.io index")
     }
 
+    /// Update the crates.io index, showing a spinner meanwhile.
+    pub fn update_index_with_progress(&mut self) -> anyhow::Result<()> {
+        let progress_bar = indicatif::ProgressBar::new_spinner();
+        progress_bar.set_message("Updating crates index...");
+        progress_bar.enable_steady_tick(Duration::from_millis(100));
+        let result = self._update_index();
+        progress_bar.finish_and_clear();
+        result
+    }
+
     /// Get the latest version of a crate.
     ///
     /// If the crate has no normal versions, the highest version will be returned,
@@ -53,7 +262,7 @@ impl CratesRegistry {
         let crate_ = self
             .index
             .crate_(crate_name)
-            .context(format!("Failed to find crate: {}", crate_name))?;
+            .ok_or_else(|| CapError::NotFound(crate_name.to_string()))?;
         let latest_version = crate_
             .highest_normal_version()
             .unwrap_or_else(|| crate_.highest_version());
@@ -61,6 +270,27 @@ impl CratesRegistry {
             .context(format!("Failed to parse version of crate: {}", crate_name))
     }
 
+    /// Get the latest version of a crate that matches a version requirement.
+    pub fn get_latest_matching_version(
+        &self,
+        crate_name: impl AsRef<str>,
+        requirement: &semver::VersionReq,
+    ) -> anyhow::Result<semver::Version> {
+        let crate_name = crate_name.as_ref();
+        let crate_ = self
+            .index
+            .crate_(crate_name)
+            .ok_or_else(|| CapError::NotFound(crate_name.to_string()))?;
+        crate_
+            .versions()
+            .iter()
+            .filter(|version| !version.is_yanked())
+            .filter_map(|version| semver::Version::parse(version.version()).ok())
+            .filter(|version| requirement.matches(version))
+            .max()
+            .ok_or_else(|| CapError::NotFound(format!("{}@{}", crate_name, requirement)).into())
+    }
+
     /// Search for crates that match a regex.
     pub fn search(&self, crate_name: impl Into<Cow<'static, str>>) -> anyhow::Result<Vec<Package>> {
         let regex = Regex::new(crate_name.into().as_ref())?;
@@ -74,21 +304,101 @@ impl CratesRegistry {
                     .then(|| crate_.highest_normal_version())
                     .flatten()
                     .and_then(|version| semver::Version::parse(version.version()).ok())
-                    .map(|version| Package::new(crate_.name().to_string(), version, vec![]))
+                    .map(|version| {
+                        Package::new(
+                            crate_.name().to_string(),
+                            version,
+                            PackageSource::crates_io(),
+                            vec![],
+                        )
+                    })
             })
             .collect())
     }
 
+    /// Get the names of all crates in the index that start with a prefix.
+    ///
+    /// Reading every crate is slow, so the names are cached in the state dir
+    /// until the index is fetched at another commit.
+    pub fn crate_names(&self, prefix: &str) -> Vec<String> {
+        let cache_path = crate::state::state_dir()
+            .map(|state_dir| state_dir.join("crate-names.txt"))
+            .ok();
+        let cache_key = crate::git::fetched_head(self.index.path())
+            .map(|head| format!("# {} {}", self.index.url(), head));
+
+        let cached_names =
+            cache_path
+                .as_ref()
+                .zip(cache_key.as_ref())
+                .and_then(|(cache_path, cache_key)| {
+                    let cache = std::fs::read_to_string(cache_path).ok()?;
+                    let (key, names) = cache.split_once('\n')?;
+                    (key == cache_key).then(|| names.to_string())
+                });
+        let names = match cached_names {
+            Some(names) => names,
+            None => {
+                let mut names = self
+                    .index
+                    .crates_parallel()
+                    .flatten()
+                    .map(|crate_| crate_.name().to_string())
+                    .collect::<Vec<_>>();
+                names.sort();
+                let names = names.join("\n");
+                // Completions still work without the cache, so failing to write it is fine.
+                if let (Some(cache_path), Some(cache_key)) = (&cache_path, &cache_key) {
+                    let _ = cache_path
+                        .parent()
+                        .map_or(Ok(()), std::fs::create_dir_all)
+                        .and_then(|_| {
+                            std::fs::write(cache_path, format!("{}\n{}", cache_key, names))
+                        });
+                }
+                names
+            }
+        };
+
+        names
+            .lines()
+            .filter(|name| name.starts_with(prefix))
+            .map(str::to_string)
+            .collect()
+    }
+
     pub fn get_crate(&self, crate_name: impl AsRef<str>) -> anyhow::Result<crates_index::Crate> {
         let crate_name = crate_name.as_ref();
         self.index
             .crate_(crate_name)
-            .context(format!("Failed to find crate on crates.io: {}", crate_name))
+            .ok_or_else(|| CapError::NotFound(crate_name.to_string()).into())
     }
 }
 
-impl Default for CratesRegistry {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{response, serve};
+
+    #[test]
+    fn sparse_index_config() {
+        let config_json =
+            r#"{"dl": "https://mirror.example.com/dl", "api": "https://mirror.example.com"}"#;
+        let (base_url, server) = serve(vec![
+            response("200 OK", "", config_json),
+            response("404 Not Found", "", ""),
+        ]);
+        let index_url = format!("{}/index/", base_url);
+
+        let config = CratesRegistry::sparse_index_config(&index_url).unwrap();
+        assert_eq!(config.api.as_deref(), Some("https://mirror.example.com"));
+        let err = CratesRegistry::sparse_index_config(&index_url).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CapError>(),
+            Some(CapError::Network)
+        ));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /index/config.json "));
     }
 }
@@ -0,0 +1,645 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use anyhow::Context;
+use crates_index::DependencyKind;
+use owo_colors::OwoColorize;
+use semver::{Version, VersionReq};
+
+use super::{target_cfg, CratesRegistry};
+use crate::{cargo::BuildOptions, error::CapError};
+
+/// A crate at a resolved version.
+type Node = (String, Version);
+
+/// The versions of a crate that are semver compatible with each other, like 1.2 and 1.5,
+/// or 0.3.1 and 0.3.4. Cargo builds at most one version per bucket.
+type Bucket = (u64, u64, u64);
+
+/// The requirements on each compatibility bucket of each crate.
+type BucketRequirements = BTreeMap<(String, Bucket), Vec<VersionReq>>;
+
+/// How often the graph is resolved again with the requirements found so far.
+const MAX_PASSES: usize = 8;
+
+fn compatibility_bucket(version: &Version) -> Bucket {
+    match (version.major, version.minor) {
+        (0, 0) => (0, 0, version.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+/// Whether a version of a crate matches all requirements on its compatibility bucket.
+fn matches_bucket(crate_name: &str, version: &Version, requirements: &BucketRequirements) -> bool {
+    let key = (crate_name.to_string(), compatibility_bucket(version));
+    requirements
+        .get(&key)
+        .into_iter()
+        .flatten()
+        .all(|requirement| requirement.matches(version))
+}
+
+/// Pick the highest version of a crate that matches a requirement, and all
+/// other requirements on its compatibility bucket.
+///
+/// Returns `None` if no version matches all of them.
+fn highest_compatible_version(
+    crate_: &crates_index::Crate,
+    requirement: &VersionReq,
+    requirements: &BucketRequirements,
+) -> Option<Version> {
+    let mut candidates = crate_
+        .versions()
+        .iter()
+        .filter(|version| !version.is_yanked())
+        .filter_map(|version| Version::parse(version.version()).ok())
+        .filter(|version| requirement.matches(version))
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .rev()
+        .find(|version| matches_bucket(crate_.name(), version, requirements))
+}
+
+/// The features that a crate version enables for its dependencies.
+#[derive(Debug, Default)]
+struct Activation {
+    /// Optional dependencies that are enabled, by dependency name.
+    optional_dependencies: BTreeSet<String>,
+    /// Features enabled on dependencies, by dependency name.
+    dependency_features: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Get the optional dependencies of a version that have an implicit feature.
+///
+/// Optional dependencies get a feature of the same name, unless any
+/// feature refers to them with the `dep:` syntax.
+fn implicit_features(version: &crates_index::Version) -> BTreeSet<&str> {
+    let explicit_dependencies = version
+        .features()
+        .values()
+        .flatten()
+        .filter_map(|value| value.strip_prefix("dep:"))
+        .collect::<BTreeSet<_>>();
+    version
+        .dependencies()
+        .iter()
+        .filter(|dependency| dependency.is_optional())
+        .map(|dependency| dependency.name())
+        .filter(|name| !explicit_dependencies.contains(name))
+        .collect()
+}
+
+/// Enable features of a crate version and everything they enable in turn.
+fn activate(version: &crates_index::Version, features: &BTreeSet<String>) -> Activation {
+    let implicit_features = implicit_features(version);
+    let mut activation = Activation::default();
+    let mut enabled_features = BTreeSet::new();
+    let mut weak_features = Vec::new();
+    let mut pending_features = features.iter().cloned().collect::<Vec<_>>();
+
+    while let Some(feature) = pending_features.pop() {
+        if !enabled_features.insert(feature.clone()) {
+            continue;
+        }
+        let Some(values) = version.features().get(&feature) else {
+            if implicit_features.contains(feature.as_str()) {
+                activation.optional_dependencies.insert(feature);
+            }
+            continue;
+        };
+        for value in values {
+            if let Some(dependency) = value.strip_prefix("dep:") {
+                activation
+                    .optional_dependencies
+                    .insert(dependency.to_string());
+            } else if let Some((dependency, dependency_feature)) = value.split_once('/') {
+                // `dep?/feature` only applies if the dependency is enabled otherwise
+                if let Some(dependency) = dependency.strip_suffix('?') {
+                    weak_features.push((dependency, dependency_feature));
+                    continue;
+                }
+                activation
+                    .optional_dependencies
+                    .insert(dependency.to_string());
+                activation
+                    .dependency_features
+                    .entry(dependency.to_string())
+                    .or_default()
+                    .insert(dependency_feature.to_string());
+                if implicit_features.contains(dependency) {
+                    pending_features.push(dependency.to_string());
+                }
+            } else {
+                pending_features.push(value.clone());
+            }
+        }
+    }
+
+    for (dependency, dependency_feature) in weak_features {
+        let is_required = version
+            .dependencies()
+            .iter()
+            .any(|candidate| candidate.name() == dependency && !candidate.is_optional());
+        if is_required || activation.optional_dependencies.contains(dependency) {
+            activation
+                .dependency_features
+                .entry(dependency.to_string())
+                .or_default()
+                .insert(dependency_feature.to_string());
+        }
+    }
+    activation
+}
+
+/// The transitive normal dependencies of a crate, as cargo would build them on this host.
+#[derive(Debug)]
+pub struct DependencyGraph {
+    root: Node,
+    dependencies: BTreeMap<Node, BTreeSet<Node>>,
+    /// The native libraries that crates link to.
+    links: BTreeMap<Node, String>,
+    /// Whether some requirements couldn't be unified, so cargo may resolve other versions.
+    is_approximate: bool,
+}
+
+impl DependencyGraph {
+    /// Get all crates in the graph, except the root.
+    fn crates(&self) -> impl Iterator<Item = &Node> {
+        self.dependencies.keys().filter(|node| **node != self.root)
+    }
+
+    /// Whether a crate is in the graph in two semver compatible versions,
+    /// which cargo never builds.
+    fn has_bucket_conflicts(&self) -> bool {
+        let mut buckets = BTreeSet::new();
+        self.dependencies
+            .keys()
+            .any(|(name, version)| !buckets.insert((name, compatibility_bucket(version))))
+    }
+
+    pub fn print_tree(&self) {
+        let (name, version) = &self.root;
+        println!("{} {}", name.blue(), version.to_string().bright_black());
+        let mut printed = BTreeSet::from([self.root.clone()]);
+        self.print_children(&self.root, "", &mut printed);
+    }
+
+    fn print_children(&self, node: &Node, prefix: &str, printed: &mut BTreeSet<Node>) {
+        let Some(children) = self.dependencies.get(node) else {
+            return;
+        };
+        for (index, child) in children.iter().enumerate() {
+            let is_last = index + 1 == children.len();
+            let (branch, indent) = if is_last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let (name, version) = child;
+            let has_children = self
+                .dependencies
+                .get(child)
+                .is_some_and(|children| !children.is_empty());
+
+            // Subtrees are shown once, later occurrences are marked instead
+            let is_repeated = !printed.insert(child.clone());
+            println!(
+                "{}{}{} {}{}",
+                prefix,
+                branch,
+                name,
+                version.to_string().bright_black(),
+                if is_repeated && has_children {
+                    " (*)"
+                } else {
+                    ""
+                }
+            );
+            if !is_repeated {
+                self.print_children(child, &format!("{}{}", prefix, indent), printed);
+            }
+        }
+    }
+
+    pub fn print_summary(&self) {
+        if self.is_approximate {
+            println!(
+                "{}",
+                "Some requirements could not be unified, cargo may resolve other versions."
+                    .yellow()
+            );
+        }
+
+        let crate_count = self.crates().count();
+        println!(
+            "{} {}",
+            crate_count.cyan(),
+            crate::helper::pluralize("unique dependency", "unique dependencies", crate_count)
+        );
+
+        let mut versions_by_name: BTreeMap<&str, Vec<&Version>> = BTreeMap::new();
+        for (name, version) in self.crates() {
+            versions_by_name.entry(name).or_default().push(version);
+        }
+        let duplicates = versions_by_name
+            .iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .collect::<Vec<_>>();
+        if !duplicates.is_empty() {
+            println!(
+                "{} {}",
+                duplicates.len().yellow(),
+                crate::helper::pluralize(
+                    "crate in multiple versions",
+                    "crates in multiple versions",
+                    duplicates.len()
+                )
+            );
+            for (name, versions) in duplicates {
+                let versions_text = versions
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!("    {} {}", name, versions_text.bright_black());
+            }
+        }
+
+        let sys_crates = self
+            .crates()
+            .filter(|(name, _)| name.ends_with("-sys"))
+            .collect::<Vec<_>>();
+        if !sys_crates.is_empty() {
+            println!(
+                "{} {}",
+                sys_crates.len().magenta(),
+                crate::helper::pluralize("-sys crate", "-sys crates", sys_crates.len())
+            );
+            for node in sys_crates {
+                let (name, version) = node;
+                let links_text = match self.links.get(node) {
+                    Some(links) => format!(" (links {})", links),
+                    None => String::default(),
+                };
+                println!(
+                    "    {} {}{}",
+                    name,
+                    version.to_string().bright_black(),
+                    links_text.bright_black()
+                );
+            }
+        }
+    }
+}
+
+/// Resolves dependency graphs offline, from the crates index.
+///
+/// Like cargo, the highest version matching a requirement is picked, unless
+/// a version already in the graph matches it, and semver compatible versions
+/// of a crate are unified into one. Features are unified per crate
+/// version, and dev dependencies and dependencies for other platforms are left out.
+pub struct DependencyResolver<'a> {
+    registry: &'a CratesRegistry,
+    crates: HashMap<String, crates_index::Crate>,
+}
+
+impl<'a> DependencyResolver<'a> {
+    pub fn new(registry: &'a CratesRegistry) -> Self {
+        Self {
+            registry,
+            crates: HashMap::new(),
+        }
+    }
+
+    fn get_crate(&mut self, crate_name: &str) -> anyhow::Result<&crates_index::Crate> {
+        if !self.crates.contains_key(crate_name) {
+            let crate_ = self.registry.get_crate(crate_name)?;
+            self.crates.insert(crate_name.to_string(), crate_);
+        }
+        Ok(&self.crates[crate_name])
+    }
+
+    fn get_version(&mut self, node: &Node) -> anyhow::Result<crates_index::Version> {
+        let (name, version) = node;
+        let version_str = version.to_string();
+        self.get_crate(name)?
+            .versions()
+            .iter()
+            .find(|candidate| candidate.version() == version_str)
+            .cloned()
+            .ok_or_else(|| CapError::NotFound(format!("{}@{}", name, version)).into())
+    }
+
+    /// Pick the version of a crate for a requirement.
+    ///
+    /// A version already in the graph is preferred. Returns whether the version
+    /// also matches the other requirements on its compatibility bucket.
+    fn select_version(
+        &mut self,
+        crate_name: &str,
+        requirement: &VersionReq,
+        selected: &BTreeMap<String, BTreeSet<Version>>,
+        requirements: &BucketRequirements,
+    ) -> anyhow::Result<(Version, bool)> {
+        let selected_version = selected.get(crate_name).and_then(|versions| {
+            versions.iter().rev().find(|version| {
+                requirement.matches(version) && matches_bucket(crate_name, version, requirements)
+            })
+        });
+        if let Some(version) = selected_version {
+            return Ok((version.clone(), true));
+        }
+
+        let crate_ = self.get_crate(crate_name)?;
+        if let Some(version) = highest_compatible_version(crate_, requirement, requirements) {
+            return Ok((version, true));
+        }
+        // Cargo would backtrack here, the highest matching version has to do instead
+        highest_compatible_version(crate_, requirement, &BucketRequirements::new())
+            .map(|version| (version, false))
+            .ok_or_else(|| CapError::NotFound(format!("{}@{}", crate_name, requirement)).into())
+    }
+
+    /// Resolve the dependencies of a crate built with the given features.
+    ///
+    /// Without a version, the latest one is used.
+    ///
+    /// Versions are picked as the requirements turn up, so a later requirement may
+    /// rule out a version picked for an earlier one in the same compatibility bucket.
+    /// The graph is resolved again with all requirements known from the last pass,
+    /// until no new ones turn up.
+    pub fn resolve(
+        &mut self,
+        crate_name: &str,
+        version: Option<&Version>,
+        options: &BuildOptions,
+    ) -> anyhow::Result<DependencyGraph> {
+        let root_crate = self.get_crate(crate_name)?;
+        let root_version = match version {
+            Some(version) => version.clone(),
+            None => {
+                let latest_version = root_crate
+                    .highest_normal_version()
+                    .unwrap_or_else(|| root_crate.highest_version());
+                Version::parse(latest_version.version())?
+            }
+        };
+        let root = (root_crate.name().to_string(), root_version);
+        let root_features = self.root_features(&root, options)?;
+
+        let mut requirements = BucketRequirements::new();
+        for _ in 0..MAX_PASSES {
+            let (mut graph, found_requirements) =
+                self.resolve_pass(&root, &root_features, &requirements)?;
+            let is_complete = found_requirements.iter().all(|(key, found)| {
+                let known = requirements.get(key);
+                found
+                    .iter()
+                    .all(|requirement| known.is_some_and(|known| known.contains(requirement)))
+            });
+            if is_complete {
+                graph.is_approximate |= graph.has_bucket_conflicts();
+                return Ok(graph);
+            }
+            for (key, found) in found_requirements {
+                let known = requirements.entry(key).or_default();
+                for requirement in found {
+                    if !known.contains(&requirement) {
+                        known.push(requirement);
+                    }
+                }
+            }
+        }
+
+        let (mut graph, _) = self.resolve_pass(&root, &root_features, &requirements)?;
+        graph.is_approximate = true;
+        Ok(graph)
+    }
+
+    /// Resolve the graph once, picking versions that match the known requirements.
+    ///
+    /// Returns the graph and the requirements found on each compatibility bucket.
+    fn resolve_pass(
+        &mut self,
+        root: &Node,
+        root_features: &BTreeSet<String>,
+        requirements: &BucketRequirements,
+    ) -> anyhow::Result<(DependencyGraph, BucketRequirements)> {
+        let mut features = BTreeMap::from([(root.clone(), root_features.clone())]);
+        let mut selected: BTreeMap<String, BTreeSet<Version>> = BTreeMap::new();
+        selected
+            .entry(root.0.clone())
+            .or_default()
+            .insert(root.1.clone());
+        let mut dependencies: BTreeMap<Node, BTreeSet<Node>> = BTreeMap::new();
+        let mut links = BTreeMap::new();
+        let mut found_requirements = BucketRequirements::new();
+        let mut is_approximate = false;
+
+        // Crates are visited again whenever more of their features are enabled
+        let mut pending_nodes = VecDeque::from([root.clone()]);
+        while let Some(node) = pending_nodes.pop_front() {
+            let version = self.get_version(&node)?;
+            if let Some(native_library) = version.links() {
+                links.insert(node.clone(), native_library.to_string());
+            }
+            let activation = activate(&version, &features[&node]);
+            let mut children = dependencies.get(&node).cloned().unwrap_or_default();
+
+            for dependency in version.dependencies() {
+                let is_enabled = !dependency.is_optional()
+                    || activation.optional_dependencies.contains(dependency.name());
+                let is_for_host = dependency.target().is_none_or(target_cfg::matches_host);
+                if dependency.kind() != DependencyKind::Normal || !is_enabled || !is_for_host {
+                    continue;
+                }
+
+                let requirement =
+                    VersionReq::parse(dependency.requirement()).with_context(|| {
+                        format!(
+                            "Invalid requirement {} for dependency {} of {}.",
+                            dependency.requirement(),
+                            dependency.crate_name(),
+                            node.0
+                        )
+                    })?;
+                let (child_version, is_unified) = self.select_version(
+                    dependency.crate_name(),
+                    &requirement,
+                    &selected,
+                    requirements,
+                )?;
+                is_approximate |= !is_unified;
+                let child = (dependency.crate_name().to_string(), child_version);
+                selected
+                    .entry(child.0.clone())
+                    .or_default()
+                    .insert(child.1.clone());
+                let bucket_requirements = found_requirements
+                    .entry((child.0.clone(), compatibility_bucket(&child.1)))
+                    .or_default();
+                if !bucket_requirements.contains(&requirement) {
+                    bucket_requirements.push(requirement);
+                }
+                children.insert(child.clone());
+
+                let mut child_features = dependency
+                    .features()
+                    .iter()
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                if dependency.has_default_features() {
+                    child_features.insert("default".to_string());
+                }
+                if let Some(extra_features) = activation.dependency_features.get(dependency.name())
+                {
+                    child_features.extend(extra_features.iter().cloned());
+                }
+
+                let is_new = !features.contains_key(&child);
+                let enabled_features = features.entry(child.clone()).or_default();
+                let feature_count = enabled_features.len();
+                enabled_features.extend(child_features);
+                if is_new || enabled_features.len() > feature_count {
+                    pending_nodes.push_back(child);
+                }
+            }
+            dependencies.insert(node, children);
+        }
+
+        let graph = DependencyGraph {
+            root: root.clone(),
+            dependencies,
+            links,
+            is_approximate,
+        };
+        Ok((graph, found_requirements))
+    }
+
+    /// Get the features the root crate is built with, checking that they exist.
+    fn root_features(
+        &mut self,
+        root: &Node,
+        options: &BuildOptions,
+    ) -> anyhow::Result<BTreeSet<String>> {
+        let version = self.get_version(root)?;
+        let implicit_features = implicit_features(&version);
+        let mut features = options.features.clone();
+        if options.all_features {
+            features.extend(version.features().keys().cloned());
+            features.extend(implicit_features.iter().map(|name| name.to_string()));
+        }
+        if !options.no_default_features {
+            features.insert("default".to_string());
+        }
+
+        for feature in &options.features {
+            let is_known = version.features().contains_key(feature)
+                || implicit_features.contains(feature.as_str())
+                || feature.contains('/');
+            if !is_known {
+                anyhow::bail!("Package {} {} has no feature {}.", root.0, root.1, feature);
+            }
+        }
+        Ok(features)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    fn requirement(requirement: &str) -> VersionReq {
+        VersionReq::parse(requirement).unwrap()
+    }
+
+    fn test_crate(versions: &[(&str, bool)]) -> crates_index::Crate {
+        let lines = versions
+            .iter()
+            .map(|(version, yanked)| {
+                format!(
+                    r#"{{"name":"foo","vers":"{}","deps":[],"cksum":"{}","features":{{}},"yanked":{}}}"#,
+                    version,
+                    "0".repeat(64),
+                    yanked
+                )
+            })
+            .collect::<Vec<_>>();
+        crates_index::Crate::from_slice(lines.join("\n").as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn compatibility_buckets() {
+        assert_eq!(compatibility_bucket(&version("1.2.3")), (1, 0, 0));
+        assert_eq!(compatibility_bucket(&version("1.9.0")), (1, 0, 0));
+        assert_eq!(compatibility_bucket(&version("0.3.1")), (0, 3, 0));
+        assert_eq!(compatibility_bucket(&version("0.0.4")), (0, 0, 4));
+    }
+
+    #[test]
+    fn highest_compatible_version_skips_yanked_versions() {
+        let crate_ = test_crate(&[("1.0.0", false), ("1.1.0", false), ("1.2.0", true)]);
+        assert_eq!(
+            highest_compatible_version(&crate_, &requirement("^1.0"), &BucketRequirements::new()),
+            Some(version("1.1.0"))
+        );
+    }
+
+    #[test]
+    fn highest_compatible_version_unifies_bucket_requirements() {
+        let crate_ = test_crate(&[("1.0.0", false), ("1.3.0", false), ("1.4.0", false)]);
+        let requirements = BucketRequirements::from([(
+            ("foo".to_string(), (1, 0, 0)),
+            vec![requirement("=1.3.0")],
+        )]);
+
+        // A later `=1.3.0` rules out 1.4.0 for an earlier `^1.0`, whatever the visit order
+        assert_eq!(
+            highest_compatible_version(&crate_, &requirement("^1.0"), &requirements),
+            Some(version("1.3.0"))
+        );
+        assert_eq!(
+            highest_compatible_version(&crate_, &requirement("^1.4"), &requirements),
+            None
+        );
+    }
+
+    #[test]
+    fn bucket_requirements_only_apply_to_their_bucket() {
+        let crate_ = test_crate(&[("0.3.5", false), ("0.4.1", false)]);
+        let requirements = BucketRequirements::from([(
+            ("foo".to_string(), (0, 3, 0)),
+            vec![requirement("=0.3.5")],
+        )]);
+        assert_eq!(
+            highest_compatible_version(&crate_, &requirement(">=0.3"), &requirements),
+            Some(version("0.4.1"))
+        );
+    }
+
+    #[test]
+    fn graphs_with_compatible_duplicates_conflict() {
+        let root = ("root".to_string(), version("1.0.0"));
+        let graph = |versions: &[&str]| {
+            let mut dependencies = BTreeMap::from([(root.clone(), BTreeSet::new())]);
+            for foo_version in versions {
+                dependencies.insert(("foo".to_string(), version(foo_version)), BTreeSet::new());
+            }
+            DependencyGraph {
+                root: root.clone(),
+                dependencies,
+                links: BTreeMap::new(),
+                is_approximate: false,
+            }
+        };
+        assert!(!graph(&["0.3.1", "0.4.0", "1.0.0"]).has_bucket_conflicts());
+        assert!(graph(&["1.0.0", "1.2.0"]).has_bucket_conflicts());
+        assert!(graph(&["0.3.1", "0.3.2"]).has_bucket_conflicts());
+    }
+}
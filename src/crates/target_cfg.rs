@@ -0,0 +1,185 @@
+use std::env::consts::{ARCH, FAMILY, OS};
+
+/// Get the value of a `key = "value"` predicate on the host.
+fn host_value(key: &str) -> Option<String> {
+    let value = match key {
+        "target_os" => OS,
+        "target_family" => FAMILY,
+        "target_arch" => ARCH,
+        "target_pointer_width" => return Some(usize::BITS.to_string()),
+        "target_endian" if cfg!(target_endian = "little") => "little",
+        "target_endian" => "big",
+        "target_env" if cfg!(target_env = "gnu") => "gnu",
+        "target_env" if cfg!(target_env = "musl") => "musl",
+        "target_env" if cfg!(target_env = "msvc") => "msvc",
+        "target_env" => "",
+        "target_vendor" if cfg!(target_vendor = "apple") => "apple",
+        "target_vendor" if cfg!(target_vendor = "pc") => "pc",
+        "target_vendor" => "unknown",
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
+/// Whether the target of a platform specific dependency applies to the host.
+///
+/// Targets are either `cfg(...)` expressions, like `cfg(all(unix, not(target_os = "macos")))`,
+/// or target triples, like `x86_64-pc-windows-msvc`.
+pub fn matches_host(target: &str) -> bool {
+    let target = target.trim();
+    match target
+        .strip_prefix("cfg(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        Some(expression) => {
+            let mut parser = CfgParser::new(expression);
+            parser.expression().unwrap_or(false) && parser.is_done()
+        }
+        None => {
+            let os = match OS {
+                "macos" => "darwin",
+                os => os,
+            };
+            target.starts_with(ARCH) && target.contains(os)
+        }
+    }
+}
+
+/// A recursive descent parser that evaluates `cfg` expressions as it goes.
+struct CfgParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> CfgParser<'a> {
+    fn new(expression: &'a str) -> Self {
+        Self { rest: expression }
+    }
+
+    fn is_done(&self) -> bool {
+        self.rest.trim().is_empty()
+    }
+
+    fn eat(&mut self, token: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Option<&'a str> {
+        self.rest = self.rest.trim_start();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (identifier, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(identifier)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        if !self.eat('"') {
+            return None;
+        }
+        let (value, rest) = self.rest.split_once('"')?;
+        self.rest = rest;
+        Some(value)
+    }
+
+    /// Parse a comma separated list of expressions in parentheses.
+    fn list(&mut self) -> Option<Vec<bool>> {
+        if !self.eat('(') {
+            return None;
+        }
+        let mut values = Vec::new();
+        while !self.eat(')') {
+            values.push(self.expression()?);
+            if !self.eat(',') {
+                self.eat(')').then_some(())?;
+                break;
+            }
+        }
+        Some(values)
+    }
+
+    fn expression(&mut self) -> Option<bool> {
+        let identifier = self.identifier()?;
+        match identifier {
+            "all" => Some(self.list()?.into_iter().all(|value| value)),
+            "any" => Some(self.list()?.into_iter().any(|value| value)),
+            "not" => match self.list()?.as_slice() {
+                [value] => Some(!value),
+                _ => None,
+            },
+            _ if self.eat('=') => {
+                let value = self.string()?;
+                Some(host_value(identifier).is_some_and(|host_value| host_value == value))
+            }
+            // Flags like `unix`, but also `test` or `debug_assertions`, which are off for installs
+            _ => Some(identifier == FAMILY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cfg_flags() {
+        assert!(matches_host(&format!("cfg({})", FAMILY)));
+        assert!(!matches_host("cfg(test)"));
+        assert!(!matches_host("cfg(debug_assertions)"));
+    }
+
+    #[test]
+    fn cfg_key_values() {
+        assert!(matches_host(&format!("cfg(target_os = \"{}\")", OS)));
+        assert!(matches_host(&format!("cfg(target_arch=\"{}\")", ARCH)));
+        assert!(!matches_host("cfg(target_os = \"plan9\")"));
+        assert!(!matches_host("cfg(unknown_key = \"value\")"));
+    }
+
+    #[test]
+    fn cfg_combinators() {
+        let host_os = format!("target_os = \"{}\"", OS);
+        assert!(matches_host(&format!(
+            "cfg(all({}, not(target_os = \"plan9\")))",
+            host_os
+        )));
+        assert!(matches_host(&format!(
+            "cfg(any(target_os = \"plan9\", {}))",
+            host_os
+        )));
+        assert!(!matches_host(&format!("cfg(not({}))", host_os)));
+        assert!(matches_host("cfg(all())"));
+        assert!(!matches_host("cfg(any())"));
+        assert!(matches_host(&format!("cfg(all({},))", host_os)));
+    }
+
+    #[test]
+    fn malformed_cfgs_never_match() {
+        assert!(!matches_host("cfg(not(unix, windows))"));
+        assert!(!matches_host("cfg(all(unix)"));
+        assert!(!matches_host("cfg(target_os = \"linux)"));
+        assert!(!matches_host("cfg(unix windows)"));
+        assert!(!matches_host("cfg()"));
+    }
+
+    #[test]
+    fn target_triples() {
+        let os = match OS {
+            "macos" => "darwin",
+            os => os,
+        };
+        assert!(matches_host(&format!("{}-unknown-{}-gnu", ARCH, os)));
+        assert!(!matches_host("wasm32-unknown-unknown"));
+    }
+}
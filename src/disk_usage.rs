@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+
+use crate::{helper::format_size, local_crates::cargo_bin_dir, Package, PackageTree};
+
+/// Get the size of a file, or `None` if it doesn't exist.
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::symlink_metadata(path)
+        .ok()
+        .map(|metadata| metadata.len())
+}
+
+/// Get the total size of all files in a dir.
+///
+/// Symlinks aren't followed and unreadable entries are skipped.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+/// Get the total size of the binaries of a package in the bin dir.
+pub fn binaries_size(package: &Package, bin_dir: &Path) -> u64 {
+    package
+        .binaries()
+        .iter()
+        .filter_map(|binary| file_size(&bin_dir.join(binary.name())))
+        .sum()
+}
+
+/// Whether a file name in the registry caches, like `ripgrep-14.0.3.crate`,
+/// belongs to a crate.
+fn is_crate_file(file_name: &str, crate_name: &str) -> bool {
+    file_name
+        .trim_end_matches(".crate")
+        .strip_prefix(crate_name)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|version| semver::Version::parse(version).is_ok())
+}
+
+/// The entries of all registries in a cache dir, like `~/.cargo/registry/cache`.
+///
+/// Each registry has its own subdir, named after its host and a hash.
+pub fn registry_cache_entries(cache_dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(registry_dirs) = std::fs::read_dir(cache_dir) else {
+        return Vec::new();
+    };
+    registry_dirs
+        .filter_map(Result::ok)
+        .filter_map(|registry_dir| std::fs::read_dir(registry_dir.path()).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            )
+        })
+        .collect()
+}
+
+/// Disk space used by an installed package.
+struct PackageUsage<'a> {
+    name: &'a str,
+    binaries: u64,
+    /// Downloaded `.crate` files of all versions.
+    downloads: u64,
+    /// Extracted sources of all versions.
+    sources: u64,
+}
+
+impl PackageUsage<'_> {
+    fn total(&self) -> u64 {
+        self.binaries + self.downloads + self.sources
+    }
+}
+
+pub struct DiskUsage<'a> {
+    packages: &'a PackageTree,
+}
+
+impl<'a> DiskUsage<'a> {
+    pub fn new(packages: &'a PackageTree) -> Self {
+        Self { packages }
+    }
+
+    /// List the installed packages with the size of their binaries.
+    pub fn print_binary_sizes(&self, short: bool) -> anyhow::Result<()> {
+        let bin_dir = cargo_bin_dir()?;
+        let packages = self
+            .packages
+            .packages()
+            .map(|package| {
+                let binaries = package
+                    .binaries()
+                    .iter()
+                    .map(|binary| (binary, file_size(&bin_dir.join(binary.name()))))
+                    .collect::<Vec<_>>();
+                let size = binaries.iter().filter_map(|(_, size)| *size).sum::<u64>();
+                (package, binaries, size)
+            })
+            .collect::<Vec<_>>();
+        for (package, binaries, size) in &packages {
+            let size_text = format!("[{}]", format_size(*size));
+            if short {
+                let binary_names = binaries
+                    .iter()
+                    .map(|(binary, _)| binary.name())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!(
+                    "{} (v{}): {} {}",
+                    package.name(),
+                    package.version(),
+                    binary_names,
+                    size_text.bright_black()
+                );
+            } else {
+                println!(
+                    "{} (v{}) {}",
+                    package.name(),
+                    package.version(),
+                    size_text.bright_black()
+                );
+                for (binary, size) in binaries {
+                    let size_text = match size {
+                        Some(size) => format_size(*size),
+                        None => "missing".to_string(),
+                    };
+                    println!(
+                        "  {} {}",
+                        binary.name(),
+                        format!("[{}]", size_text).bright_black()
+                    );
+                }
+            }
+        }
+
+        let total = packages.iter().map(|(_, _, size)| size).sum::<u64>();
+        println!(
+            "Total: {} in {} {}",
+            format_size(total).bold(),
+            packages.len(),
+            crate::helper::pluralize("package", "packages", packages.len())
+        );
+        Ok(())
+    }
+
+    /// Print a table of the disk space used by each package, largest first.
+    ///
+    /// Besides the binaries, this counts the `.crate` files cargo downloaded
+    /// and the sources it extracted into `~/.cargo/registry` while installing.
+    pub fn print_report(&self) -> anyhow::Result<()> {
+        let bin_dir = cargo_bin_dir()?;
+        let registry_dir = home::cargo_home()
+            .context("Unable to find cargo home dir.")?
+            .join("registry");
+        let downloads = registry_cache_entries(&registry_dir.join("cache"));
+        let sources = registry_cache_entries(&registry_dir.join("src"));
+
+        let mut usages = self
+            .packages
+            .packages()
+            .map(|package| PackageUsage {
+                name: package.name(),
+                binaries: binaries_size(package, &bin_dir),
+                downloads: downloads
+                    .iter()
+                    .filter(|(file_name, _)| is_crate_file(file_name, package.name()))
+                    .filter_map(|(_, path)| file_size(path))
+                    .sum(),
+                sources: sources
+                    .iter()
+                    .filter(|(file_name, _)| is_crate_file(file_name, package.name()))
+                    .map(|(_, path)| dir_size(path))
+                    .sum(),
+            })
+            .collect::<Vec<_>>();
+        usages.sort_by(|a, b| b.total().cmp(&a.total()).then(a.name.cmp(b.name)));
+
+        let total = PackageUsage {
+            name: "Total",
+            binaries: usages.iter().map(|usage| usage.binaries).sum(),
+            downloads: usages.iter().map(|usage| usage.downloads).sum(),
+            sources: usages.iter().map(|usage| usage.sources).sum(),
+        };
+        Self::print_table(&usages, &total);
+        Ok(())
+    }
+
+    fn print_table(usages: &[PackageUsage], total: &PackageUsage) {
+        const HEADERS: [&str; 5] = ["Package", "Binaries", "Downloads", "Sources", "Total"];
+        let cells = usages
+            .iter()
+            .chain([total])
+            .map(|usage| {
+                [
+                    usage.name.to_string(),
+                    format_size(usage.binaries),
+                    format_size(usage.downloads),
+                    format_size(usage.sources),
+                    format_size(usage.total()),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let mut widths = HEADERS.map(|header| header.chars().count());
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        // The name column is left aligned, the sizes are right aligned
+        let format_row = |row: &[String; 5]| {
+            let [name, sizes @ ..] = row;
+            let mut line = format!("{:width$}", name, width = widths[0]);
+            for (size, width) in sizes.iter().zip(&widths[1..]) {
+                line.push_str(&format!("  {:>width$}", size, width = width));
+            }
+            line
+        };
+        let header_line = format_row(&HEADERS.map(str::to_string));
+        println!("{}", header_line.bold());
+        let (total_row, package_rows) = cells.split_last().expect("Total row is always present");
+        for row in package_rows {
+            let line = format_row(row);
+            let (name, sizes) = line.split_at(widths[0]);
+            println!("{}{}", name.blue(), sizes);
+        }
+        println!("{}", format_row(total_row).bold());
+    }
+}
@@ -0,0 +1,453 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use crates_index::GitIndex;
+use owo_colors::OwoColorize;
+
+use crate::{
+    local_crates::{cargo_bin_dir, install_root, CratesMetadataJson, CratesMetadataToml},
+    CratesIoClient, CratesRegistry, PackageTree,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Note,
+    Warning,
+    Error,
+}
+
+/// The result of a single health check.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    severity: Severity,
+    message: String,
+    hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Ok,
+            message: message.to_string(),
+            hint: None,
+        }
+    }
+
+    fn note(message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Note,
+            message: message.to_string(),
+            hint: None,
+        }
+    }
+
+    fn problem(severity: Severity, message: impl ToString, hint: impl ToString) -> Self {
+        Self {
+            severity,
+            message: message.to_string(),
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    /// Whether this finding means that something is wrong.
+    pub fn is_problem(&self) -> bool {
+        self.severity >= Severity::Warning
+    }
+
+    fn print(&self) {
+        let label = match self.severity {
+            Severity::Ok => format!("{}", " ok ".green()),
+            Severity::Note => format!("{}", "note".cyan()),
+            Severity::Warning => format!("{}", "warn".yellow()),
+            Severity::Error => format!("{}", "fail".red()),
+        };
+        println!("[{}] {}", label, self.message);
+        if let Some(hint) = &self.hint {
+            println!("       {} {}", "fix:".bright_black(), hint.bright_black());
+        }
+    }
+}
+
+/// Checks the environment cap runs in for common problems.
+pub struct Doctor<'a> {
+    packages: &'a PackageTree,
+    offline: bool,
+}
+
+impl<'a> Doctor<'a> {
+    pub fn new(packages: &'a PackageTree, offline: bool) -> Self {
+        Self { packages, offline }
+    }
+
+    /// Run all checks and print the findings.
+    ///
+    /// Returns `true` if no problems were found.
+    pub fn run(&self) -> anyhow::Result<bool> {
+        let bin_dir = cargo_bin_dir()?;
+        let path_dirs = std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut findings = Vec::new();
+        findings.extend(self.check_bin_dir_on_path(&bin_dir, &path_dirs));
+        findings.extend(self.check_shadowed_binaries(&bin_dir, &path_dirs));
+        findings.extend(self.check_metadata_agreement());
+        findings.extend(self.check_index());
+        findings.extend(self.check_toolchains());
+        if !self.offline {
+            findings.extend(self.check_registry_reachable());
+        }
+
+        for finding in &findings {
+            finding.print();
+        }
+
+        let problem_count = findings.iter().filter(|f| f.is_problem()).count();
+        if problem_count == 0 {
+            println!("No problems found.");
+        } else {
+            println!(
+                "Found {} {}.",
+                problem_count.red(),
+                crate::helper::pluralize("problem", "problems", problem_count)
+            );
+        }
+
+        Ok(problem_count == 0)
+    }
+
+    fn check_bin_dir_on_path(&self, bin_dir: &Path, path_dirs: &[PathBuf]) -> Vec<Finding> {
+        if path_dirs.iter().any(|dir| is_same_dir(dir, bin_dir)) {
+            return vec![Finding::ok(format!(
+                "Cargo bin dir {} is on PATH",
+                bin_dir.display()
+            ))];
+        }
+        vec![Finding::problem(
+            Severity::Error,
+            format!("Cargo bin dir {} is not on PATH", bin_dir.display()),
+            format!(
+                "Add it to your shell profile: export PATH=\"{}:$PATH\"",
+                bin_dir.display()
+            ),
+        )]
+    }
+
+    fn check_shadowed_binaries(&self, bin_dir: &Path, path_dirs: &[PathBuf]) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for package in self.packages.packages() {
+            for binary in package.binaries() {
+                // Find the first PATH entry that provides this binary
+                let Some(first_dir) = path_dirs
+                    .iter()
+                    .find(|dir| dir.join(binary.name()).is_file())
+                else {
+                    continue;
+                };
+                if is_same_dir(first_dir, bin_dir) {
+                    continue;
+                }
+                findings.push(Finding::problem(
+                    Severity::Warning,
+                    format!(
+                        "{} from package {} is shadowed by {}",
+                        binary.name().blue(),
+                        package.name().blue(),
+                        first_dir.join(binary.name()).display()
+                    ),
+                    format!(
+                        "Move {} before {} in PATH, or remove the other binary",
+                        bin_dir.display(),
+                        first_dir.display()
+                    ),
+                ));
+            }
+        }
+        if findings.is_empty() {
+            findings.push(Finding::ok("No installed binaries are shadowed"));
+        }
+        findings
+    }
+
+    fn check_metadata_agreement(&self) -> Vec<Finding> {
+        // Cargo only creates its metadata with the first install
+        let has_crates_toml = install_root().is_ok_and(|root| root.join(".crates.toml").exists());
+        if !has_crates_toml {
+            return vec![Finding::note(
+                "No .crates.toml yet, nothing was installed with cargo",
+            )];
+        }
+
+        let crates_toml = match CratesMetadataToml::load() {
+            Ok(crates_toml) => crates_toml,
+            Err(err) => {
+                return vec![Finding::problem(
+                    Severity::Warning,
+                    format!("Unable to read .crates.toml: {}", err),
+                    "Install any package with cargo to recreate it",
+                )]
+            }
+        };
+        let crates_json = match CratesMetadataJson::load() {
+            Ok(crates_json) => crates_json,
+            Err(err) => {
+                return vec![Finding::problem(
+                    Severity::Warning,
+                    format!("Unable to read .crates2.json: {}", err),
+                    "Reinstall your packages with cap install --forced <package>",
+                )]
+            }
+        };
+
+        let mut findings = Vec::new();
+        let keys = crates_toml
+            .packages()
+            .keys()
+            .chain(crates_json.installs().keys())
+            .collect::<BTreeSet<_>>();
+        for key in keys {
+            let toml_bins = crates_toml
+                .packages()
+                .get(key)
+                .map(|bins| bins.iter().collect::<BTreeSet<_>>());
+            let json_bins = crates_json
+                .installs()
+                .get(key)
+                .map(|record| record.bins.iter().collect::<BTreeSet<_>>());
+            let package_name = key.split(' ').next().unwrap_or(key);
+            let message = match (toml_bins, json_bins) {
+                (Some(_), None) => format!("{} is only listed in .crates.toml", key),
+                (None, Some(_)) => format!("{} is only listed in .crates2.json", key),
+                (Some(toml_bins), Some(json_bins)) if toml_bins != json_bins => {
+                    format!(
+                        "{} lists different binaries in .crates.toml and .crates2.json",
+                        key
+                    )
+                }
+                _ => continue,
+            };
+            findings.push(Finding::problem(
+                Severity::Error,
+                message,
+                format!(
+                    "Reinstall the package with cap install --forced {}",
+                    package_name
+                ),
+            ));
+        }
+        if findings.is_empty() {
+            findings.push(Finding::ok(".crates.toml and .crates2.json agree"));
+        }
+        findings
+    }
+
+    fn check_index(&self) -> Vec<Finding> {
+        let index_url = match CratesRegistry::index_url() {
+            Ok(index_url) => index_url,
+            Err(err) => {
+                return vec![Finding::problem(
+                    Severity::Error,
+                    format!("Unable to read the cargo config: {:#}", err),
+                    "Fix the syntax of the cargo config",
+                )]
+            }
+        };
+        if index_url.starts_with("sparse+") {
+            return vec![Finding::problem(
+                Severity::Error,
+                format!(
+                    "crates.io is replaced by the sparse registry {}, which cap can't read",
+                    index_url
+                ),
+                "Replace crates.io with a git mirror of the index",
+            )];
+        }
+
+        match GitIndex::try_from_url(&index_url) {
+            Ok(Some(index)) => {
+                if index.crate_("serde").is_some() {
+                    vec![Finding::ok(format!(
+                        "Crates index at {} is usable",
+                        index.path().display()
+                    ))]
+                } else {
+                    vec![Finding::problem(
+                        Severity::Error,
+                        format!(
+                            "Crates index at {} looks incomplete",
+                            index.path().display()
+                        ),
+                        "Run cap index repair",
+                    )]
+                }
+            }
+            Ok(None) => vec![Finding::problem(
+                Severity::Warning,
+                "Crates index has not been fetched yet",
+                "Run cap update to fetch it",
+            )],
+            Err(err) => vec![Finding::problem(
+                Severity::Error,
+                format!("Unable to open the crates index: {}", err),
+                "Run cap index repair",
+            )],
+        }
+    }
+
+    fn check_toolchains(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        match command_output("cargo", &["--version"]) {
+            Some(version) => findings.push(Finding::ok(format!("Found {}", version.trim()))),
+            None => findings.push(Finding::problem(
+                Severity::Error,
+                "Unable to run cargo",
+                "Install Rust from https://rustup.rs",
+            )),
+        }
+
+        let Some(toolchains) = command_output("rustup", &["toolchain", "list"]) else {
+            findings.push(Finding::problem(
+                Severity::Warning,
+                "Unable to run rustup",
+                "Install rustup from https://rustup.rs to manage toolchains",
+            ));
+            return findings;
+        };
+
+        if toolchains.lines().any(|line| line.contains("default")) {
+            findings.push(Finding::ok("A default rustup toolchain is set"));
+        } else {
+            findings.push(Finding::problem(
+                Severity::Error,
+                "No default rustup toolchain is set",
+                "Run rustup default stable",
+            ));
+        }
+
+        if toolchains.lines().any(|line| line.starts_with("nightly")) {
+            findings.push(Finding::ok("A nightly toolchain is installed"));
+        } else {
+            findings.push(Finding::problem(
+                Severity::Note,
+                "No nightly toolchain is installed, cap install --nightly won't work",
+                "Run rustup toolchain install nightly",
+            ));
+        }
+
+        findings
+    }
+
+    fn check_registry_reachable(&self) -> Vec<Finding> {
+        let index_url =
+            CratesRegistry::index_url().unwrap_or_else(|_| crates_index::git::URL.to_string());
+        let api_url = CratesIoClient::new()
+            .map(|client| client.base_url().to_string())
+            .unwrap_or_else(|_| "https://crates.io".to_string());
+        let endpoints = [
+            ("crates.io index", index_probe_url(&index_url)),
+            ("crates.io API", Some(format!("{}/api/v1/summary", api_url))),
+        ];
+        endpoints
+            .into_iter()
+            .filter_map(|(name, url)| Some((name, url?)))
+            .map(|(name, url)| match probe_url(&url) {
+                Ok(code) if code >= 400 => Finding::problem(
+                    Severity::Error,
+                    format!("{} responds with HTTP status {} at {}", name, code, url),
+                    "Check the registry URL in the cargo config and your proxy settings",
+                ),
+                Ok(_) => Finding::ok(format!("{} is reachable", name)),
+                Err(err) => Finding::problem(
+                    Severity::Error,
+                    format!("{} is not reachable: {}", name, err),
+                    "Check your network and proxy settings, or pass --offline",
+                ),
+            })
+            .collect()
+    }
+}
+
+/// Compare two directories, resolving symlinks where possible.
+fn is_same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Run a command and return its stdout, if it succeeded.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Get a URL that an index serves over HTTP.
+///
+/// Sparse indexes serve their `config.json`, git indexes the refs for smart HTTP.
+/// Indexes that aren't served over HTTP, like local or SSH ones, have none.
+fn index_probe_url(index_url: &str) -> Option<String> {
+    let (index_url, file_name) = match index_url.strip_prefix("sparse+") {
+        Some(index_url) => (index_url, "config.json"),
+        None => (index_url, "info/refs?service=git-upload-pack"),
+    };
+    let is_http = index_url.starts_with("https://") || index_url.starts_with("http://");
+    is_http.then(|| format!("{}/{}", index_url.trim_end_matches('/'), file_name))
+}
+
+/// Send a HEAD request and return the status code of the final response.
+fn probe_url(url: &str) -> anyhow::Result<u32> {
+    let mut handle = crate::http::handle()?;
+    handle.url(url)?;
+    handle.follow_location(true)?;
+    handle.nobody(true)?;
+    handle.timeout(Duration::from_secs(10))?;
+    handle.perform()?;
+    Ok(handle.response_code()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{response, serve};
+
+    #[test]
+    fn index_probe_urls() {
+        assert_eq!(
+            index_probe_url("https://github.com/rust-lang/crates.io-index").as_deref(),
+            Some("https://github.com/rust-lang/crates.io-index/info/refs?service=git-upload-pack")
+        );
+        assert_eq!(
+            index_probe_url("sparse+https://index.crates.io/").as_deref(),
+            Some("https://index.crates.io/config.json")
+        );
+        assert_eq!(index_probe_url("file:///srv/crates.io-index"), None);
+        assert_eq!(index_probe_url("ssh://git@example.com/index"), None);
+    }
+
+    #[test]
+    fn probes_return_the_status_code() {
+        let (base_url, server) = serve(vec![
+            response("200 OK", "", ""),
+            response("403 Forbidden", "", ""),
+        ]);
+        assert_eq!(
+            probe_url(&format!("{}/config.json", base_url)).unwrap(),
+            200
+        );
+        assert_eq!(
+            probe_url(&format!("{}/config.json", base_url)).unwrap(),
+            403
+        );
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("HEAD /config.json "));
+    }
+}
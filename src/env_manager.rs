@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    local_crates::install_root,
+    state::{CapState, Environment, DEFAULT_ENV_NAME},
+};
+
+/// Print the shell line that puts a bin dir on PATH.
+fn print_path_snippet(bin_dir: &Path) {
+    println!("To run its binaries, add its bin dir to PATH in your shell:");
+    println!("  export PATH=\"{}:$PATH\"", bin_dir.display());
+}
+
+pub fn create_env(name: &str, toolchain: Option<String>) -> anyhow::Result<()> {
+    let env = Environment::create(name, toolchain)?;
+    println!(
+        "{} environment {} in {}.",
+        "Created".green(),
+        env.name().blue(),
+        env.dir().display()
+    );
+    println!(
+        "Run {} to manage packages in it.",
+        format!("cap env use {}", env.name()).bright_black()
+    );
+    Ok(())
+}
+
+/// Make an environment the active one.
+///
+/// The name `default` switches back to the regular install root.
+pub fn use_env(name: &str) -> anyhow::Result<()> {
+    let mut state = CapState::load()?;
+
+    if name == DEFAULT_ENV_NAME {
+        state.active_env = None;
+        state.save()?;
+        println!("{} the default install root.", "Using".green());
+        print_path_snippet(&install_root()?.join("bin"));
+        return Ok(());
+    }
+
+    let env = Environment::load(name)?;
+    state.active_env = Some(env.name().to_string());
+    state.save()?;
+    println!("{} environment {}.", "Using".green(), env.name().blue());
+    print_path_snippet(&env.bin_dir());
+    Ok(())
+}
+
+pub fn list_envs() -> anyhow::Result<()> {
+    let active_name = CapState::load()?.active_env;
+    let is_active = |name: &str| active_name.as_deref().unwrap_or(DEFAULT_ENV_NAME) == name;
+
+    let marker = |name: &str| if is_active(name) { "*" } else { " " };
+    println!("{} {}", marker(DEFAULT_ENV_NAME), DEFAULT_ENV_NAME.blue());
+    for env in Environment::list()? {
+        let manifest = env.manifest();
+        let mut details = Vec::new();
+        if let Some(toolchain) = &manifest.toolchain {
+            details.push(format!("toolchain {}", toolchain));
+        }
+        if !manifest.pins.is_empty() {
+            details.push(format!(
+                "{} {}",
+                manifest.pins.len(),
+                crate::helper::pluralize("pin", "pins", manifest.pins.len())
+            ));
+        }
+        let details_text = if details.is_empty() {
+            String::default()
+        } else {
+            format!(" ({})", details.join(", "))
+        };
+        println!(
+            "{} {}{}",
+            marker(env.name()),
+            env.name().blue(),
+            details_text.bright_black()
+        );
+    }
+    Ok(())
+}
+
+fn active_env_or_bail() -> anyhow::Result<Environment> {
+    match Environment::active()? {
+        Some(env) => Ok(env),
+        None => anyhow::bail!("No environment is active. Run cap env use <name> first."),
+    }
+}
+
+/// Keep a package within a version requirement in the active environment.
+pub fn pin_package(package_name: &str, requirement: &str) -> anyhow::Result<()> {
+    let requirement = semver::VersionReq::parse(requirement)?;
+    let mut env = active_env_or_bail()?;
+    env.manifest_mut()
+        .pins
+        .insert(package_name.to_string(), requirement.clone());
+    env.save()?;
+    println!(
+        "{} {} to {} in environment {}.",
+        "Pinned".green(),
+        package_name.blue(),
+        requirement.bright_black(),
+        env.name().blue()
+    );
+    Ok(())
+}
+
+pub fn unpin_package(package_name: &str) -> anyhow::Result<()> {
+    let mut env = active_env_or_bail()?;
+    if env.manifest_mut().pins.remove(package_name).is_none() {
+        println!(
+            "Package {} is not pinned in environment {}.",
+            package_name.blue(),
+            env.name().blue()
+        );
+        return Ok(());
+    }
+    env.save()?;
+    println!(
+        "{} {} in environment {}.",
+        "Unpinned".green(),
+        package_name.blue(),
+        env.name().blue()
+    );
+    Ok(())
+}
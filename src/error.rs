@@ -0,0 +1,49 @@
+/// Exit code for a successful run.
+pub const EXIT_SUCCESS: u8 = 0;
+/// Exit code for errors that have no more specific exit code.
+pub const EXIT_FAILURE: u8 = 1;
+/// Exit code when the license policy denies a package, also used by `cap licenses`.
+pub const EXIT_LICENSE_DENIED: u8 = 8;
+/// Exit code of `cap check` when updates are available.
+pub const EXIT_UPDATES_AVAILABLE: u8 = 10;
+/// Exit code of `cap audit` when advisories affect installed packages.
+pub const EXIT_ADVISORIES_FOUND: u8 = 11;
+
+/// Errors that scripts calling cap may want to tell apart.
+///
+/// These are carried inside `anyhow::Error`, either as the error itself
+/// or as context, and are mapped to exit codes in `main`.
+#[derive(Debug, thiserror::Error)]
+pub enum CapError {
+    #[error("Package {0} is not available on crates.io")]
+    NotFound(String),
+    #[error("Package {0} is not installed")]
+    NotInstalled(String),
+    #[error("Failed to build package {0}")]
+    BuildFailed(String),
+    #[error("The crates.io index is unavailable")]
+    IndexUnavailable,
+    #[error("A network request failed")]
+    Network,
+    #[error("License {1} of package {0} is not allowed")]
+    LicenseDenied(String, String),
+    #[error("Source {0} is not supported")]
+    UnsupportedSource(String),
+}
+
+impl CapError {
+    /// The process exit code for this error.
+    ///
+    /// Exit code 2 is used by clap for invalid command line arguments.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CapError::NotFound(_) => 3,
+            CapError::NotInstalled(_) => 4,
+            CapError::BuildFailed(_) => 5,
+            CapError::IndexUnavailable => 6,
+            CapError::Network => 7,
+            CapError::LicenseDenied(_, _) => EXIT_LICENSE_DENIED,
+            CapError::UnsupportedSource(_) => 9,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{path::Path, process::Command};
+
+use anyhow::Context;
+
+use crate::{error::CapError, local_crates::GitReference};
+
+/// Get the commit that a branch or tag currently points to in a remote repository.
+///
+/// Returns `None` for a pinned rev, which never moves.
+pub fn remote_head(url: &str, reference: &GitReference) -> anyhow::Result<Option<String>> {
+    let pattern = match reference {
+        GitReference::DefaultBranch => "HEAD".to_string(),
+        GitReference::Branch(branch) => format!("refs/heads/{}", branch),
+        GitReference::Tag(tag) => format!("refs/tags/{}", tag),
+        GitReference::Rev(_) => return Ok(None),
+    };
+
+    // Annotated tags point to a tag object, the peeled `^{}` ref holds the commit.
+    let peeled_pattern = format!("{}^{{}}", pattern);
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg(url)
+        .arg(&pattern)
+        .arg(&peeled_pattern)
+        .output()
+        .context("Unable to run git.")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().next().unwrap_or_default().trim().to_string();
+        return Err(CapError::Network)
+            .with_context(|| format!("Unable to query {}: {}", url, message));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let refs = stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect::<Vec<_>>();
+    let commit = refs
+        .iter()
+        .find(|(_, name)| *name == peeled_pattern)
+        .or_else(|| refs.iter().find(|(_, name)| *name == pattern))
+        .map(|(commit, _)| commit.to_string());
+    match commit {
+        Some(commit) => Ok(Some(commit)),
+        None => anyhow::bail!("Unable to find {} in {}.", reference, url),
+    }
+}
+
+/// Get the commit a local repository was last fetched at.
+///
+/// The refs are tried in the order the crates index reads them in.
+pub fn fetched_head(repository: &Path) -> Option<String> {
+    [
+        "FETCH_HEAD",
+        "refs/remotes/origin/HEAD",
+        "refs/remotes/origin/master",
+    ]
+    .into_iter()
+    .find_map(|reference| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(repository)
+            .args(["rev-parse", "--verify", "--quiet"])
+            .arg(format!("{}^{{commit}}", reference))
+            .output()
+            .ok()?;
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !commit.is_empty()).then_some(commit)
+    })
+}
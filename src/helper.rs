@@ -1,3 +1,9 @@
+mod duration;
 mod pluralize;
+mod prompt;
+mod size;
 
+pub use duration::{format_age, format_duration};
 pub use pluralize::pluralize;
+pub use prompt::{ask, confirm, is_interactive};
+pub use size::format_size;
@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+/// Describe how long ago something happened, like `3 hours ago`.
+pub fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    let (count, singular, plural) = match minutes {
+        0 => return "just now".to_string(),
+        1..=59 => (minutes, "minute", "minutes"),
+        60..=1439 => (minutes / 60, "hour", "hours"),
+        _ => (minutes / 1440, "day", "days"),
+    };
+    format!(
+        "{} {} ago",
+        count,
+        super::pluralize(singular, plural, count as usize)
+    )
+}
+
+/// Describe a duration like `1m 32s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,28 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, Write},
+};
+
+/// Check whether cap is running in an interactive terminal session.
+pub fn is_interactive() -> bool {
+    termion::is_tty(&std::io::stdin()) && termion::is_tty(&std::io::stdout())
+}
+
+/// Ask the user a question on the terminal and return the trimmed answer.
+pub fn ask(prompt: impl Display) -> anyhow::Result<String> {
+    print!("{} ", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(answer.trim().to_string())
+}
+
+/// Ask the user a yes/no question on the terminal.
+///
+/// Anything other than an explicit `y` or `yes` is treated as a no.
+pub fn confirm(prompt: impl Display) -> anyhow::Result<bool> {
+    let answer = ask(format!("{} [y/N]", prompt))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{read_to_string, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{local_crates::PackageExecutable, PackageTree};
+
+/// Format of history timestamps shown to the user.
+const DISPLAY_FORMAT: &[time::format_description::FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second] UTC");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryAction {
+    Install,
+    Update,
+    Reinstall,
+    Uninstall,
+}
+
+impl Display for HistoryAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Install => write!(f, "install"),
+            Self::Update => write!(f, "update"),
+            Self::Reinstall => write!(f, "reinstall"),
+            Self::Uninstall => write!(f, "uninstall"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryOutcome {
+    Success,
+    Failed,
+}
+
+/// A change that cap made to an installed package.
+///
+/// The history is stored as `history.jsonl` in the cap state dir,
+/// with one entry per line, oldest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix timestamp of the end of the change.
+    pub timestamp: u64,
+    pub action: HistoryAction,
+    pub package: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_features: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_default_features: bool,
+    /// The binaries that were removed, if not the whole package was.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub binaries: Vec<String>,
+    /// Whether the build used the lockfile of the package.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// How long the build took, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    pub outcome: HistoryOutcome,
+    /// The build log of the change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+}
+
+impl HistoryEntry {
+    pub fn new(action: HistoryAction, package_name: &str) -> Self {
+        Self {
+            timestamp: 0,
+            action,
+            package: package_name.to_string(),
+            from: None,
+            to: None,
+            toolchain: None,
+            features: Vec::new(),
+            all_features: false,
+            no_default_features: false,
+            binaries: Vec::new(),
+            locked: false,
+            duration: None,
+            outcome: HistoryOutcome::Success,
+            log: None,
+        }
+    }
+
+    fn path() -> anyhow::Result<PathBuf> {
+        Ok(crate::state::state_dir()?.join("history.jsonl"))
+    }
+
+    /// Append the entry to the history, stamped with the current time.
+    ///
+    /// Parallel updates append concurrently, so writes are serialized.
+    pub fn append(mut self, outcome: HistoryOutcome) -> anyhow::Result<()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        self.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.outcome = outcome;
+
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("Unable to create cap state dir.")?;
+        }
+        let mut line = serde_json::to_string(&self)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .context("Unable to write history.")
+    }
+
+    /// Load the whole history, oldest first.
+    ///
+    /// Lines that can't be parsed are skipped.
+    pub fn load_all() -> anyhow::Result<Vec<Self>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let history_str = read_to_string(&path).context("Unable to read history.")?;
+        Ok(history_str
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    fn time_text(&self) -> String {
+        OffsetDateTime::from_unix_timestamp(self.timestamp as i64)
+            .ok()
+            .and_then(|time| time.format(DISPLAY_FORMAT).ok())
+            .unwrap_or_default()
+    }
+
+    fn change_text(&self) -> String {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) if from != to => format!("{} -> {}", from, to),
+            (_, Some(version)) | (Some(version), None) => version.clone(),
+            (None, None) => String::default(),
+        }
+    }
+
+    /// Describe the toolchain and features of the build, if they aren't the defaults,
+    /// or the binaries that were removed.
+    fn build_text(&self) -> String {
+        let mut details = Vec::new();
+        if !self.binaries.is_empty() {
+            details.push(format!("binaries {}", self.binaries.join(",")));
+        }
+        if let Some(toolchain) = &self.toolchain {
+            details.push(format!("+{}", toolchain));
+        }
+        if self.all_features {
+            details.push("all features".to_string());
+        }
+        if self.no_default_features {
+            details.push("no default features".to_string());
+        }
+        if !self.features.is_empty() {
+            details.push(format!("features {}", self.features.join(",")));
+        }
+        if self.locked {
+            details.push("locked".to_string());
+        }
+        details.join(", ")
+    }
+}
+
+/// Show the history of all packages, or of a single one.
+pub fn show_history(package_name: Option<&str>) -> anyhow::Result<()> {
+    let entries = HistoryEntry::load_all()?
+        .into_iter()
+        .filter(|entry| package_name.is_none_or(|name| entry.package == name))
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        match package_name {
+            Some(name) => println!("No history found for package {}.", name.blue()),
+            None => println!("No history found."),
+        }
+        return Ok(());
+    }
+
+    let package_padding = entries
+        .iter()
+        .map(|entry| entry.package.chars().count())
+        .max()
+        .unwrap_or(0);
+    let change_padding = entries
+        .iter()
+        .map(|entry| entry.change_text().chars().count())
+        .max()
+        .unwrap_or(0);
+    for entry in &entries {
+        let outcome_text = match entry.outcome {
+            HistoryOutcome::Success => format!("{:<6}", "ok").green().to_string(),
+            HistoryOutcome::Failed => format!("{:<6}", "failed").red().to_string(),
+        };
+        let duration_text = entry
+            .duration
+            .map(|duration| crate::helper::format_duration(Duration::from_secs_f64(duration)))
+            .unwrap_or_default();
+        println!(
+            "{}  {:<9}  {:package_padding$}  {:change_padding$}  {}  {:>7}  {}",
+            entry.time_text().cyan(),
+            entry.action,
+            entry.package.blue(),
+            entry.change_text(),
+            outcome_text,
+            duration_text,
+            entry.build_text().bright_black(),
+            package_padding = package_padding,
+            change_padding = change_padding,
+        );
+        if let (HistoryOutcome::Failed, Some(log)) = (entry.outcome, &entry.log) {
+            println!("  Build log: {}", log.display().bright_black());
+        }
+    }
+    Ok(())
+}
+
+/// When a package was last changed successfully and how long it usually takes to build.
+#[derive(Debug, Default)]
+struct PackageTimes {
+    last_updated: Option<u64>,
+    build_durations: Vec<f64>,
+}
+
+impl PackageTimes {
+    /// Get the median duration of successful builds.
+    fn typical_build_time(&self) -> Option<Duration> {
+        let mut durations = self.build_durations.clone();
+        durations.sort_by(f64::total_cmp);
+        let median = durations.get(durations.len() / 2)?;
+        Some(Duration::from_secs_f64(*median))
+    }
+
+    fn text(&self) -> String {
+        let mut details = Vec::new();
+        if let Some(last_updated) = self.last_updated {
+            let age = SystemTime::now()
+                .duration_since(UNIX_EPOCH + Duration::from_secs(last_updated))
+                .unwrap_or_default();
+            details.push(format!("updated {}", crate::helper::format_age(age)));
+        }
+        if let Some(build_time) = self.typical_build_time() {
+            details.push(format!(
+                "builds in {}",
+                crate::helper::format_duration(build_time)
+            ));
+        }
+        if details.is_empty() {
+            return "no history".to_string();
+        }
+        details.join(", ")
+    }
+}
+
+/// List the installed packages with when they were last updated and their typical build time.
+pub fn print_package_times(packages: &PackageTree, short: bool) -> anyhow::Result<()> {
+    let mut times: BTreeMap<String, PackageTimes> = BTreeMap::new();
+    for entry in HistoryEntry::load_all()? {
+        if entry.outcome != HistoryOutcome::Success || entry.action == HistoryAction::Uninstall {
+            continue;
+        }
+        let package_times = times.entry(entry.package).or_default();
+        package_times.last_updated = Some(entry.timestamp);
+        package_times.build_durations.extend(entry.duration);
+    }
+
+    for package in packages.packages() {
+        let times_text = times
+            .get(package.name())
+            .map(PackageTimes::text)
+            .unwrap_or_else(|| "no history".to_string());
+        let binaries = package
+            .binaries()
+            .iter()
+            .map(PackageExecutable::name)
+            .collect::<Vec<_>>();
+        if short {
+            println!(
+                "{} (v{}): {} {}",
+                package.name(),
+                package.version(),
+                binaries.join(", "),
+                format!("({})", times_text).bright_black()
+            );
+        } else {
+            println!(
+                "{} (v{}) {}",
+                package.name(),
+                package.version(),
+                format!("({})", times_text).bright_black()
+            );
+            for binary in binaries {
+                println!("  {}", binary);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::{path::PathBuf, time::Duration};
+
+use curl::easy::Easy;
+
+use crate::cargo_config::CargoConfig;
+
+const USER_AGENT: &str = "cap package manager (github.com/splittydev/cap)";
+
+/// Cargo's default for `http.timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Cargo's HTTP settings, which cap applies to all of its network access.
+///
+/// Like cargo, the `CARGO_HTTP_*` environment variables take precedence
+/// over the `[http]` section of the cargo config.
+#[derive(Debug, Default)]
+pub struct HttpConfig {
+    proxy: Option<String>,
+    cainfo: Option<PathBuf>,
+    timeout: Option<Duration>,
+}
+
+impl HttpConfig {
+    pub fn load() -> anyhow::Result<Self> {
+        let config = CargoConfig::load()?;
+        let env_var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let proxy = env_var("CARGO_HTTP_PROXY")
+            .or_else(|| config.get_str("http.proxy").map(str::to_string))
+            .filter(|proxy| !proxy.is_empty());
+        let cainfo = env_var("CARGO_HTTP_CAINFO")
+            .map(PathBuf::from)
+            .or_else(|| config.get_path("http.cainfo"));
+        let timeout = env_var("CARGO_HTTP_TIMEOUT")
+            .or_else(|| env_var("HTTP_TIMEOUT"))
+            .and_then(|timeout| timeout.parse().ok())
+            .or_else(|| {
+                config
+                    .get("http.timeout")
+                    .and_then(|(value, _)| value.as_integer())
+                    .and_then(|timeout| u64::try_from(timeout).ok())
+            })
+            .map(Duration::from_secs);
+
+        Ok(Self {
+            proxy,
+            cainfo,
+            timeout,
+        })
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    /// Create a curl handle with the settings applied.
+    ///
+    /// As in cargo, the timeout limits connecting and stalled transfers,
+    /// not the duration of the whole transfer.
+    pub fn handle(&self) -> Result<Easy, curl::Error> {
+        let mut handle = Easy::new();
+        handle.useragent(USER_AGENT)?;
+        if let Some(proxy) = &self.proxy {
+            handle.proxy(proxy)?;
+        }
+        if let Some(cainfo) = &self.cainfo {
+            handle.cainfo(cainfo)?;
+        }
+        handle.connect_timeout(self.timeout())?;
+        handle.low_speed_time(self.timeout())?;
+        handle.low_speed_limit(10)?;
+        Ok(handle)
+    }
+
+    /// Pass the settings on to git, which cap uses for the index and git packages.
+    ///
+    /// Both the git binary and the git library behind the index read these
+    /// environment variables. Variables that are already set are left alone.
+    pub fn export_to_git_env(&self) {
+        let mut variables = Vec::new();
+        if let Some(proxy) = &self.proxy {
+            variables.extend([
+                ("http_proxy", proxy.clone()),
+                ("https_proxy", proxy.clone()),
+                ("HTTPS_PROXY", proxy.clone()),
+            ]);
+        }
+        if let Some(cainfo) = &self.cainfo {
+            variables.push(("GIT_SSL_CAINFO", cainfo.display().to_string()));
+        }
+        variables.extend([
+            ("GIT_HTTP_LOW_SPEED_LIMIT", "10".to_string()),
+            (
+                "GIT_HTTP_LOW_SPEED_TIME",
+                self.timeout().as_secs().to_string(),
+            ),
+        ]);
+
+        for (name, value) in variables {
+            if std::env::var_os(name).is_none() {
+                std::env::set_var(name, value);
+            }
+        }
+    }
+}
+
+/// Create a curl handle with cargo's HTTP settings applied.
+pub fn handle() -> anyhow::Result<Easy> {
+    Ok(HttpConfig::load()?.handle()?)
+}
+
+/// A stand-in HTTP server for tests.
+#[cfg(test)]
+pub mod test_server {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    /// Serve one canned response per connection, and return the requests that came in.
+    ///
+    /// Returns the base URL of the server, which also works as a proxy URL.
+    pub fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(&stream);
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (base_url, server)
+    }
+
+    pub fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_server::{response, serve};
+
+    #[test]
+    fn requests_go_through_the_proxy() {
+        let (proxy_url, proxy) = serve(vec![response("200 OK", "", "{}")]);
+        let config = HttpConfig {
+            proxy: Some(proxy_url),
+            ..Default::default()
+        };
+        let mut handle = config.handle().unwrap();
+        handle
+            .url("http://index.example.invalid/config.json")
+            .unwrap();
+        handle.perform().unwrap();
+        assert_eq!(handle.response_code().unwrap(), 200);
+
+        // Proxies get the absolute URL, which never resolves without them
+        let requests = proxy.join().unwrap();
+        assert!(requests[0].starts_with("GET http://index.example.invalid/config.json "));
+        assert!(requests[0].contains(USER_AGENT));
+    }
+}
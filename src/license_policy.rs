@@ -0,0 +1,521 @@
+use std::{fmt::Display, fs::File, io::Read, path::Path};
+
+use anyhow::Context;
+use indicatif::MultiProgress;
+use owo_colors::OwoColorize;
+use semver::Version;
+use serde::Deserialize;
+
+use crate::{
+    crates::CratesIoClient,
+    disk_usage::registry_cache_entries,
+    error::CapError,
+    local_crates::PackageSource,
+    state::{CapConfig, LicensesConfig, UnlistedLicenses},
+    PackageTree,
+};
+
+/// How the license policy judges a license.
+///
+/// Ordered from worst to best, so alternatives pick the maximum
+/// and combinations the minimum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LicenseVerdict {
+    Denied,
+    Unlisted,
+    Allowed,
+}
+
+impl Display for LicenseVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Denied => write!(f, "denied"),
+            Self::Unlisted => write!(f, "unlisted"),
+            Self::Allowed => write!(f, "allowed"),
+        }
+    }
+}
+
+/// Split an SPDX license expression into tokens.
+///
+/// The old `MIT/Apache-2.0` style is read as `MIT OR Apache-2.0`.
+fn tokenize(expression: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    for word in expression.split_whitespace() {
+        let mut rest = word;
+        while !rest.is_empty() {
+            let end = rest.find(['(', ')', '/']).unwrap_or(rest.len());
+            if end == 0 {
+                let (token, remainder) = rest.split_at(1);
+                tokens.push(if token == "/" { "OR" } else { token });
+                rest = remainder;
+            } else {
+                tokens.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+    tokens
+}
+
+/// A recursive descent parser that judges SPDX expressions as it goes.
+struct LicenseParser<'a, 'p> {
+    tokens: Vec<&'a str>,
+    position: usize,
+    policy: &'p LicensePolicy,
+}
+
+impl<'a> LicenseParser<'a, '_> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek()?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn or_expression(&mut self) -> Option<LicenseVerdict> {
+        let mut verdict = self.and_expression()?;
+        while self.peek() == Some("OR") {
+            self.next();
+            verdict = verdict.max(self.and_expression()?);
+        }
+        Some(verdict)
+    }
+
+    fn and_expression(&mut self) -> Option<LicenseVerdict> {
+        let mut verdict = self.license()?;
+        while self.peek() == Some("AND") {
+            self.next();
+            verdict = verdict.min(self.license()?);
+        }
+        Some(verdict)
+    }
+
+    fn license(&mut self) -> Option<LicenseVerdict> {
+        match self.next()? {
+            "(" => {
+                let verdict = self.or_expression()?;
+                (self.next()? == ")").then_some(verdict)
+            }
+            ")" | "OR" | "AND" | "WITH" => None,
+            id => {
+                // Exceptions only widen what a license permits, so they are judged by the license
+                if self.peek() == Some("WITH") {
+                    self.next();
+                    let exception = self.next()?;
+                    let full_id = format!("{} WITH {}", id, exception);
+                    if self.policy.lists(&full_id) {
+                        return Some(self.policy.evaluate_id(&full_id));
+                    }
+                }
+                Some(self.policy.evaluate_id(id))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestPackage {
+    /// A string, or a table for licenses inherited from the workspace, which are unknown.
+    license: Option<toml::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    package: ManifestPackage,
+}
+
+/// Read the license from a `Cargo.toml`.
+fn manifest_license(manifest_str: &str) -> Option<String> {
+    toml::from_str::<Manifest>(manifest_str)
+        .ok()?
+        .package
+        .license?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Read the license from the manifest in a source dir.
+fn source_dir_license(source_dir: &Path) -> anyhow::Result<Option<String>> {
+    let manifest_path = source_dir.join("Cargo.toml");
+    let manifest_str = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Unable to read {}.", manifest_path.display()))?;
+    Ok(manifest_license(&manifest_str))
+}
+
+/// Read the license from the manifest in a `.crate` file that cargo downloaded.
+fn downloaded_crate_license(crate_name: &str, version: &Version) -> anyhow::Result<Option<String>> {
+    let Ok(cargo_home_dir) = home::cargo_home() else {
+        return Ok(None);
+    };
+    let file_name = format!("{}-{}.crate", crate_name, version);
+    let Some((_, crate_path)) = registry_cache_entries(&cargo_home_dir.join("registry/cache"))
+        .into_iter()
+        .find(|(entry_name, _)| *entry_name == file_name)
+    else {
+        return Ok(None);
+    };
+
+    let manifest_path = format!("{}-{}/Cargo.toml", crate_name, version);
+    let crate_file = File::open(&crate_path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(crate_file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_str() != Some(manifest_path.as_str()) {
+            continue;
+        }
+        let mut manifest_str = String::new();
+        entry.read_to_string(&mut manifest_str)?;
+        return Ok(manifest_license(&manifest_str));
+    }
+    Ok(None)
+}
+
+/// Get the license of a crate version.
+///
+/// The manifest in the downloaded `.crate` is used if cargo has it cached,
+/// the crates.io API otherwise.
+pub fn crate_license(crate_name: &str, version: &Version) -> anyhow::Result<Option<String>> {
+    match downloaded_crate_license(crate_name, version) {
+        Ok(Some(license)) => return Ok(Some(license)),
+        Ok(None) => (),
+        Err(err) => log::warn!("Unable to read downloaded crate {}: {:#}", crate_name, err),
+    }
+    let crate_info = CratesIoClient::new()?.get_package_info(crate_name)?;
+    Ok(crate_info
+        .version(&version.to_string())
+        .and_then(|version| version.license.clone()))
+}
+
+/// Get the license of an installed package, if it can be found.
+fn package_license(
+    name: &str,
+    version: &Version,
+    source: &PackageSource,
+) -> anyhow::Result<Option<String>> {
+    match source {
+        source @ PackageSource::Registry(_) if source.is_crates_io() => {
+            crate_license(name, version)
+        }
+        // The crates.io API doesn't know crates of other registries
+        PackageSource::Registry(_) => downloaded_crate_license(name, version),
+        PackageSource::Path(source_dir) => source_dir_license(source_dir),
+        PackageSource::Git(_) => Ok(None),
+    }
+}
+
+pub struct LicensePolicy {
+    config: LicensesConfig,
+}
+
+impl LicensePolicy {
+    /// Load the policy from the `licenses` section of the cap config.
+    pub fn load() -> anyhow::Result<Self> {
+        Ok(Self {
+            config: CapConfig::load()?.licenses,
+        })
+    }
+
+    /// Whether any license is allowed or denied.
+    pub fn is_configured(&self) -> bool {
+        !self.config.allow.is_empty() || !self.config.deny.is_empty()
+    }
+
+    fn lists(&self, id: &str) -> bool {
+        self.config
+            .allow
+            .iter()
+            .chain(&self.config.deny)
+            .any(|listed| listed.eq_ignore_ascii_case(id))
+    }
+
+    fn evaluate_id(&self, id: &str) -> LicenseVerdict {
+        let matches = |listed: &String| listed.eq_ignore_ascii_case(id);
+        if self.config.deny.iter().any(matches) {
+            LicenseVerdict::Denied
+        } else if self.config.allow.iter().any(matches) || self.config.allow.is_empty() {
+            LicenseVerdict::Allowed
+        } else {
+            LicenseVerdict::Unlisted
+        }
+    }
+
+    /// Judge a license expression, like `MIT OR Apache-2.0`.
+    ///
+    /// Packages without a license and expressions that can't be parsed are unlisted.
+    pub fn evaluate(&self, license: Option<&str>) -> LicenseVerdict {
+        let Some(license) = license else {
+            return LicenseVerdict::Unlisted;
+        };
+        let mut parser = LicenseParser {
+            tokens: tokenize(license),
+            position: 0,
+            policy: self,
+        };
+        match parser.or_expression() {
+            Some(verdict) if parser.peek().is_none() => verdict,
+            _ => LicenseVerdict::Unlisted,
+        }
+    }
+
+    /// Check the license of a crate version before installing it.
+    ///
+    /// Denied licenses are refused. Unlisted licenses are refused or warned
+    /// about, depending on `licenses.unlisted`.
+    pub fn enforce(
+        &self,
+        crate_name: &str,
+        version: &Version,
+        progress: &MultiProgress,
+    ) -> anyhow::Result<()> {
+        if !self.is_configured() {
+            return Ok(());
+        }
+        let license = crate_license(crate_name, version)
+            .with_context(|| format!("Unable to look up the license of {}.", crate_name))?;
+        self.enforce_license(crate_name, license, progress)
+    }
+
+    /// Check the license in the manifest of a path package before installing it.
+    pub fn enforce_path(
+        &self,
+        crate_name: &str,
+        source_dir: &Path,
+        progress: &MultiProgress,
+    ) -> anyhow::Result<()> {
+        if !self.is_configured() {
+            return Ok(());
+        }
+        let license = source_dir_license(source_dir)?;
+        self.enforce_license(crate_name, license, progress)
+    }
+
+    /// Check a git package before installing it.
+    ///
+    /// Only cargo fetches the repository, so the license is unknown
+    /// and judged like an unlisted one.
+    pub fn enforce_git(&self, crate_name: &str, progress: &MultiProgress) -> anyhow::Result<()> {
+        if !self.is_configured() {
+            return Ok(());
+        }
+        self.enforce_license(crate_name, None, progress)
+    }
+
+    fn enforce_license(
+        &self,
+        crate_name: &str,
+        license: Option<String>,
+        progress: &MultiProgress,
+    ) -> anyhow::Result<()> {
+        let license_text = license.clone().unwrap_or_else(|| "unknown".to_string());
+        match self.evaluate(license.as_deref()) {
+            LicenseVerdict::Allowed => Ok(()),
+            LicenseVerdict::Unlisted if self.config.unlisted == UnlistedLicenses::Warn => {
+                progress.suspend(|| {
+                    println!(
+                        "{} License {} of package {} is not on the allow list.",
+                        "Warning:".yellow(),
+                        license_text.bright_black(),
+                        crate_name.blue()
+                    )
+                });
+                Ok(())
+            }
+            _ => Err(CapError::LicenseDenied(crate_name.to_string(), license_text).into()),
+        }
+    }
+}
+
+/// List the licenses of the installed packages, judged by the license policy.
+///
+/// Returns `true` if the policy denies any of them.
+pub fn print_licenses(packages: &PackageTree) -> anyhow::Result<bool> {
+    let policy = LicensePolicy::load()?;
+    let mut rows = Vec::new();
+    for package in packages.packages() {
+        match package_license(package.name(), package.version(), package.source()) {
+            Ok(license) => rows.push((package, policy.evaluate(license.as_deref()), license)),
+            Err(err) => eprintln!(
+                "{} to look up the license of {}: {:#}",
+                "Failed".yellow(),
+                package.name().blue(),
+                err
+            ),
+        }
+    }
+
+    let name_width = rows
+        .iter()
+        .map(|(package, _, _)| package.name().chars().count())
+        .max()
+        .unwrap_or(0);
+    let version_width = rows
+        .iter()
+        .map(|(package, _, _)| package.version().to_string().chars().count())
+        .max()
+        .unwrap_or(0);
+    for (package, verdict, license) in &rows {
+        let license_text = license.as_deref().unwrap_or("unknown");
+        let verdict_text = match verdict {
+            LicenseVerdict::Allowed => verdict.green().to_string(),
+            LicenseVerdict::Unlisted => verdict.yellow().to_string(),
+            LicenseVerdict::Denied => verdict.red().to_string(),
+        };
+        let verdict_text = if policy.is_configured() {
+            format!("  ({})", verdict_text)
+        } else {
+            String::default()
+        };
+        println!(
+            "{:name_width$}  {:version_width$}  {}{}",
+            package.name().blue(),
+            package.version().to_string().bright_black(),
+            license_text,
+            verdict_text,
+            name_width = name_width,
+            version_width = version_width,
+        );
+    }
+
+    Ok(rows
+        .iter()
+        .any(|(_, verdict, _)| *verdict == LicenseVerdict::Denied))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str], unlisted: UnlistedLicenses) -> LicensePolicy {
+        LicensePolicy {
+            config: LicensesConfig {
+                allow: allow.iter().map(|id| id.to_string()).collect(),
+                deny: deny.iter().map(|id| id.to_string()).collect(),
+                unlisted,
+            },
+        }
+    }
+
+    #[test]
+    fn tokenize_expressions() {
+        assert_eq!(tokenize("MIT OR Apache-2.0"), ["MIT", "OR", "Apache-2.0"]);
+        assert_eq!(tokenize("MIT/Apache-2.0"), ["MIT", "OR", "Apache-2.0"]);
+        assert_eq!(
+            tokenize("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+            [
+                "(",
+                "MIT",
+                "OR",
+                "Apache-2.0",
+                ")",
+                "AND",
+                "Unicode-DFS-2016"
+            ]
+        );
+        assert_eq!(
+            tokenize("Apache-2.0 WITH LLVM-exception"),
+            ["Apache-2.0", "WITH", "LLVM-exception"]
+        );
+        assert!(tokenize("  ").is_empty());
+    }
+
+    #[test]
+    fn alternatives_pick_the_best_verdict() {
+        let policy = policy(&["MIT"], &["GPL-3.0"], UnlistedLicenses::Warn);
+        assert_eq!(
+            policy.evaluate(Some("MIT OR GPL-3.0")),
+            LicenseVerdict::Allowed
+        );
+        assert_eq!(
+            policy.evaluate(Some("GPL-3.0/MIT")),
+            LicenseVerdict::Allowed
+        );
+        assert_eq!(
+            policy.evaluate(Some("GPL-3.0 OR Zlib")),
+            LicenseVerdict::Unlisted
+        );
+        assert_eq!(policy.evaluate(Some("gpl-3.0")), LicenseVerdict::Denied);
+    }
+
+    #[test]
+    fn combinations_pick_the_worst_verdict() {
+        let policy = policy(&["MIT", "Apache-2.0"], &["GPL-3.0"], UnlistedLicenses::Warn);
+        assert_eq!(
+            policy.evaluate(Some("MIT AND Apache-2.0")),
+            LicenseVerdict::Allowed
+        );
+        assert_eq!(
+            policy.evaluate(Some("MIT AND Zlib")),
+            LicenseVerdict::Unlisted
+        );
+        assert_eq!(
+            policy.evaluate(Some("(MIT OR Apache-2.0) AND GPL-3.0")),
+            LicenseVerdict::Denied
+        );
+        // AND binds tighter than OR
+        assert_eq!(
+            policy.evaluate(Some("MIT OR Zlib AND GPL-3.0")),
+            LicenseVerdict::Allowed
+        );
+    }
+
+    #[test]
+    fn exceptions_are_judged_by_their_license() {
+        let policy = policy(
+            &["Apache-2.0"],
+            &["GPL-2.0 WITH Classpath-exception-2.0"],
+            UnlistedLicenses::Warn,
+        );
+        assert_eq!(
+            policy.evaluate(Some("Apache-2.0 WITH LLVM-exception")),
+            LicenseVerdict::Allowed
+        );
+        assert_eq!(
+            policy.evaluate(Some("GPL-2.0 WITH Classpath-exception-2.0")),
+            LicenseVerdict::Denied
+        );
+    }
+
+    #[test]
+    fn missing_and_malformed_licenses_are_unlisted() {
+        let policy = policy(&[], &["GPL-3.0"], UnlistedLicenses::Warn);
+        assert_eq!(policy.evaluate(Some("Zlib")), LicenseVerdict::Allowed);
+        assert_eq!(policy.evaluate(None), LicenseVerdict::Unlisted);
+        assert_eq!(policy.evaluate(Some("MIT OR")), LicenseVerdict::Unlisted);
+        assert_eq!(policy.evaluate(Some("(MIT")), LicenseVerdict::Unlisted);
+        assert_eq!(
+            policy.evaluate(Some("MIT Apache-2.0")),
+            LicenseVerdict::Unlisted
+        );
+        assert_eq!(policy.evaluate(Some("")), LicenseVerdict::Unlisted);
+    }
+
+    #[test]
+    fn git_packages_follow_the_unlisted_setting() {
+        let progress = MultiProgress::new();
+        let warn = policy(&["MIT"], &[], UnlistedLicenses::Warn);
+        assert!(warn.enforce_git("foo", &progress).is_ok());
+        let deny = policy(&["MIT"], &[], UnlistedLicenses::Deny);
+        let err = deny.enforce_git("foo", &progress).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CapError>(),
+            Some(CapError::LicenseDenied(name, _)) if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn manifest_licenses() {
+        assert_eq!(
+            manifest_license("[package]\nname = \"foo\"\nlicense = \"MIT\"\n"),
+            Some("MIT".to_string())
+        );
+        assert_eq!(
+            manifest_license("[package]\nname = \"foo\"\nlicense.workspace = true\n"),
+            None
+        );
+        assert_eq!(manifest_license("[package]\nname = \"foo\"\n"), None);
+    }
+}
@@ -1,15 +1,23 @@
 mod binary_invocation_scraper;
+mod cargo_dirs;
+mod cargo_install_metadata;
 mod cargo_metadata_scraper;
+mod orphan_scanner;
 mod package;
 mod package_executable;
 mod package_key;
+mod package_source;
 mod package_tree;
 mod scraper;
 
 pub use binary_invocation_scraper::BinaryInvocationScraper;
-pub use cargo_metadata_scraper::CargoMetadataScraper;
+pub use cargo_dirs::{cargo_bin_dir, custom_install_root, install_root};
+pub use cargo_install_metadata::CratesMetadataJson;
+pub use cargo_metadata_scraper::{CargoMetadataScraper, CratesMetadataToml};
+pub use orphan_scanner::{Orphan, OrphanKind, OrphanScanner};
 pub use package::Package;
 pub use package_executable::PackageExecutable;
 pub use package_key::PackageKey;
+pub use package_source::{GitReference, GitSource, PackageSource};
 pub use package_tree::{PackageFormatting, PackageTree};
 pub use scraper::LocalPackageMetadataScraper;
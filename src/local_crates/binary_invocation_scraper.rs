@@ -1,9 +1,11 @@
 use anyhow::Context;
 use log::trace;
 use rayon::prelude::{ParallelBridge, ParallelIterator};
-use std::{collections::HashMap, process::Command};
+use std::{collections::HashMap, path::Path, process::Command};
 
-use super::{LocalPackageMetadataScraper, PackageExecutable, PackageKey};
+use super::{
+    cargo_bin_dir, LocalPackageMetadataScraper, PackageExecutable, PackageKey, PackageSource,
+};
 
 /// Find the first semver version in a string.
 ///
@@ -31,12 +33,38 @@ fn find_version(str: &str) -> Option<semver::Version> {
 /// fallback when the cargo metadata-based approach fails.
 pub struct BinaryInvocationScraper;
 
+impl BinaryInvocationScraper {
+    /// Guess the package name and version of a binary by invoking it with `--version`.
+    pub fn identify(binary_path: &Path) -> Option<PackageKey> {
+        let output = Command::new(binary_path).arg("--version").output().ok()?;
+
+        // Get stdout as UTF-8 string
+        let output_str = String::from_utf8(output.stdout).unwrap_or_default();
+
+        // Assume that the first word of the first line is the package name
+        let package_name = output_str
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .next()?;
+
+        // Try to find a version in the output
+        let version = find_version(&output_str)?;
+
+        // Binaries don't tell where they came from, so assume crates.io
+        Some(PackageKey::new(
+            package_name.to_lowercase(),
+            version,
+            PackageSource::crates_io(),
+        ))
+    }
+}
+
 impl LocalPackageMetadataScraper for BinaryInvocationScraper {
     fn scrape() -> anyhow::Result<HashMap<PackageKey, Vec<PackageExecutable>>> {
         // Get cargo bin dir
-        let dir = home::cargo_home()
-            .map(|pb| pb.join("bin"))
-            .expect("Unable to find cargo bin dir.");
+        let dir = cargo_bin_dir()?;
 
         // Read cargo bin dir
         std::fs::read_dir(dir)
@@ -50,40 +78,16 @@ impl LocalPackageMetadataScraper for BinaryInvocationScraper {
                     .par_bridge()
                     // Build hashmap of packages and executables
                     .fold(HashMap::new, |mut map, entry| {
-                        if let Ok(output) =
-                            Command::new(entry.file_name()).arg("--version").output()
-                        {
-                            // Get stdout as UTF-8 string
-                            let output_str = String::from_utf8(output.stdout).unwrap_or_default();
+                        if let Some(package_key) = Self::identify(&entry.path()) {
+                            // Build executable info from file name
+                            let executable = PackageExecutable::new(
+                                entry.file_name().to_string_lossy().to_lowercase(),
+                            );
 
-                            // Get iterator over words of first line
-                            let mut parts_iter = output_str
-                                .lines()
-                                .next()
-                                .unwrap_or_default()
-                                .split_whitespace();
-
-                            // Assume that the first word of the first line is the package name
-                            let package_name = parts_iter.next();
-
-                            // Try to find a version in the output
-                            let version = find_version(&output_str);
-
-                            if let (Some(package_name), Some(version)) = (package_name, version) {
-                                // Build package info from version output
-                                let package_key =
-                                    PackageKey::new(package_name.to_lowercase(), version);
-
-                                // Build executable info from file name
-                                let executable = PackageExecutable::new(
-                                    entry.file_name().to_string_lossy().to_lowercase(),
-                                );
-
-                                // Add package and executable to the hashmap
-                                map.entry(package_key)
-                                    .or_insert_with(Vec::new)
-                                    .push(executable);
-                            }
+                            // Add package and executable to the hashmap
+                            map.entry(package_key)
+                                .or_insert_with(Vec::new)
+                                .push(executable);
                         }
                         map
                     })
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::{cargo_config::CargoConfig, state::Environment};
+
+/// Get the root dir that packages are installed to.
+///
+/// Like cargo, this honors `CARGO_INSTALL_ROOT` first, then `install.root`
+/// from the cargo config, and falls back to the cargo home dir. An active
+/// cap environment takes precedence over the cargo config.
+/// The global `--root` option is passed on through `CARGO_INSTALL_ROOT`.
+pub fn install_root() -> anyhow::Result<PathBuf> {
+    if let Some(root) = std::env::var_os("CARGO_INSTALL_ROOT").filter(|root| !root.is_empty()) {
+        return Ok(PathBuf::from(root));
+    }
+    if let Some(env) = Environment::active()? {
+        return Ok(env.dir().to_path_buf());
+    }
+    if let Some(root) = CargoConfig::load()?.get_path("install.root") {
+        return Ok(root);
+    }
+    home::cargo_home().context("Unable to find cargo home dir.")
+}
+
+/// Get the install root, if it differs from the cargo home dir.
+pub fn custom_install_root() -> anyhow::Result<Option<PathBuf>> {
+    let root = install_root()?;
+    let is_cargo_home = home::cargo_home().is_ok_and(|cargo_home_dir| cargo_home_dir == root);
+    Ok((!is_cargo_home).then_some(root))
+}
+
+/// Get the cargo bin dir, usually `~/.cargo/bin`.
+pub fn cargo_bin_dir() -> anyhow::Result<PathBuf> {
+    Ok(install_root()?.join("bin"))
+}
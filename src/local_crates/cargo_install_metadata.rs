@@ -0,0 +1,52 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::read_to_string,
+};
+
+use serde::Deserialize;
+
+/// Install details cargo records for a single package.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InstallRecord {
+    #[serde(default)]
+    pub bins: BTreeSet<String>,
+    #[serde(default)]
+    pub features: BTreeSet<String>,
+    #[serde(default)]
+    pub all_features: bool,
+    #[serde(default)]
+    pub no_default_features: bool,
+    /// Version string of the compiler that built the package, like `rustc 1.75.0 (...)`.
+    #[serde(default)]
+    pub rustc: Option<String>,
+}
+
+/// The `.crates2.json` file generated by cargo.
+#[derive(Debug, Deserialize)]
+pub struct CratesMetadataJson {
+    installs: BTreeMap<String, InstallRecord>,
+}
+
+impl CratesMetadataJson {
+    /// Read the `.crates2.json` file from the install root.
+    pub fn load() -> anyhow::Result<Self> {
+        let crates_json_path = super::install_root()?.join(".crates2.json");
+        let crates_json_str = read_to_string(crates_json_path)?;
+        Ok(serde_json::from_str(&crates_json_str)?)
+    }
+
+    /// Package keys, mapped to their install records.
+    ///
+    /// Keys have the form `name version (source)`, just like in `.crates.toml`.
+    pub fn installs(&self) -> &BTreeMap<String, InstallRecord> {
+        &self.installs
+    }
+
+    /// Find the install record of a package by name.
+    pub fn get(&self, package_name: &str) -> Option<&InstallRecord> {
+        self.installs
+            .iter()
+            .find(|(key, _)| key.split(' ').next() == Some(package_name))
+            .map(|(_, record)| record)
+    }
+}
@@ -1,9 +1,8 @@
-use anyhow::Context;
 use log::warn;
 use serde::Deserialize;
 use std::{collections::HashMap, fs::read_to_string};
 
-use super::{LocalPackageMetadataScraper, PackageExecutable, PackageKey};
+use super::{LocalPackageMetadataScraper, PackageExecutable, PackageKey, PackageSource};
 
 #[derive(Debug, Deserialize)]
 pub struct CratesMetadataToml {
@@ -11,6 +10,22 @@ pub struct CratesMetadataToml {
     packages: HashMap<String, Vec<String>>,
 }
 
+impl CratesMetadataToml {
+    /// Read the `.crates.toml` file from the install root.
+    pub fn load() -> anyhow::Result<Self> {
+        let crates_toml_path = super::install_root()?.join(".crates.toml");
+        let crates_toml_str = read_to_string(crates_toml_path)?;
+        Ok(toml::from_str(&crates_toml_str)?)
+    }
+
+    /// Package keys, mapped to the binaries they own.
+    ///
+    /// Keys have the form `name version (source)`.
+    pub fn packages(&self) -> &HashMap<String, Vec<String>> {
+        &self.packages
+    }
+}
+
 /// An efficient package metadata scraper that reads the
 /// `.crates.toml` file generated by cargo.
 ///
@@ -21,13 +36,8 @@ pub struct CargoMetadataScraper;
 
 impl LocalPackageMetadataScraper for CargoMetadataScraper {
     fn scrape() -> anyhow::Result<HashMap<PackageKey, Vec<PackageExecutable>>> {
-        // Get cargo .crates.toml path
-        let cargo_home_dir = home::cargo_home().context("Unable to find cargo home dir.")?;
-        let crates_toml_path = cargo_home_dir.join(".crates.toml");
-
         // Read .crates.toml file into struct
-        let crates_toml_str = read_to_string(crates_toml_path)?;
-        let metadata: CratesMetadataToml = toml::from_str(&crates_toml_str)?;
+        let metadata = CratesMetadataToml::load()?;
 
         // Transform hashmap
         let hashmap =
@@ -39,8 +49,12 @@ impl LocalPackageMetadataScraper for CargoMetadataScraper {
                     .collect::<Vec<_>>()[..]
                 {
                     [name, version, _] => {
-                        let Ok(version) = semver::Version::parse(version) else { return map };
-                        let package_key = PackageKey::new(name, version);
+                        let Ok(version) = semver::Version::parse(version) else {
+                            return map;
+                        };
+                        let source = PackageSource::from_key(&package)
+                            .unwrap_or_else(PackageSource::crates_io);
+                        let package_key = PackageKey::new(name, version, source);
                         let executables =
                             binaries.into_iter().map(PackageExecutable::new).collect();
                         map.insert(package_key, executables);
@@ -0,0 +1,136 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Context;
+
+use super::{cargo_bin_dir, PackageTree};
+
+/// Binaries that rustup installs as proxies into the cargo bin dir.
+const RUSTUP_PROXIES: &[&str] = &[
+    "rustup",
+    "cargo",
+    "cargo-clippy",
+    "cargo-fmt",
+    "cargo-miri",
+    "clippy-driver",
+    "rls",
+    "rust-analyzer",
+    "rust-gdb",
+    "rust-gdbgui",
+    "rust-lldb",
+    "rustc",
+    "rustdoc",
+    "rustfmt",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OrphanKind {
+    /// A file in the cargo bin dir that no package owns.
+    Untracked,
+    /// A rustup proxy such as `cargo` or `rustc`.
+    RustupProxy,
+    /// A binary that is tracked by cargo, but missing from the cargo bin dir.
+    Missing,
+}
+
+/// A binary that is out of sync with cargo's package metadata.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Orphan {
+    kind: OrphanKind,
+    binary: String,
+    package: Option<(String, semver::Version)>,
+    path: PathBuf,
+}
+
+impl Orphan {
+    pub fn kind(&self) -> OrphanKind {
+        self.kind
+    }
+
+    pub fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    /// The name and version of the package that tracks this binary.
+    ///
+    /// This is only set for missing binaries.
+    pub fn package(&self) -> Option<(&str, &semver::Version)> {
+        self.package
+            .as_ref()
+            .map(|(name, version)| (name.as_str(), version))
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+/// Compares the files in the cargo bin dir with the binaries
+/// of the installed packages.
+pub struct OrphanScanner;
+
+impl OrphanScanner {
+    pub fn scan(packages: &PackageTree) -> anyhow::Result<Vec<Orphan>> {
+        let bin_dir = cargo_bin_dir()?;
+        let mut orphans = Vec::new();
+
+        // Collect binaries tracked by any package
+        let tracked_binaries = packages
+            .packages()
+            .flat_map(|package| package.binaries())
+            .map(|binary| binary.name())
+            .collect::<HashSet<_>>();
+
+        // Find files that no package owns
+        let entries = std::fs::read_dir(&bin_dir).context("Unable to read cargo bin dir.")?;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if !entry
+                .file_type()
+                .map(|ft| ft.is_file() || ft.is_symlink())
+                .unwrap_or_default()
+            {
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if tracked_binaries.contains(file_name.as_str()) {
+                continue;
+            }
+            let kind = if Self::is_rustup_proxy(&file_name) {
+                OrphanKind::RustupProxy
+            } else {
+                OrphanKind::Untracked
+            };
+            orphans.push(Orphan {
+                kind,
+                binary: file_name,
+                package: None,
+                path: entry.path(),
+            });
+        }
+
+        // Find tracked binaries whose files are gone
+        for package in packages.packages() {
+            for binary in package.binaries() {
+                let path = bin_dir.join(binary.name());
+                if path.exists() {
+                    continue;
+                }
+                orphans.push(Orphan {
+                    kind: OrphanKind::Missing,
+                    binary: binary.name().to_string(),
+                    package: Some((package.name().to_string(), package.version().clone())),
+                    path,
+                });
+            }
+        }
+
+        orphans.sort();
+        Ok(orphans)
+    }
+
+    fn is_rustup_proxy(file_name: &str) -> bool {
+        let name = file_name
+            .strip_suffix(std::env::consts::EXE_SUFFIX)
+            .unwrap_or(file_name);
+        RUSTUP_PROXIES.contains(&name)
+    }
+}
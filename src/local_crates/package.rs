@@ -1,18 +1,25 @@
-use super::PackageExecutable;
+use super::{PackageExecutable, PackageSource};
 
 /// A package including name, version, and binaries.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Package {
     name: String,
     version: semver::Version,
+    source: PackageSource,
     binaries: Vec<PackageExecutable>,
 }
 
 impl Package {
-    pub fn new(name: String, version: semver::Version, binaries: Vec<PackageExecutable>) -> Self {
+    pub fn new(
+        name: String,
+        version: semver::Version,
+        source: PackageSource,
+        binaries: Vec<PackageExecutable>,
+    ) -> Self {
         Self {
             name,
             version,
+            source,
             binaries,
         }
     }
@@ -25,6 +32,10 @@ impl Package {
         &self.version
     }
 
+    pub fn source(&self) -> &PackageSource {
+        &self.source
+    }
+
     pub fn binaries(&self) -> &[PackageExecutable] {
         &self.binaries
     }
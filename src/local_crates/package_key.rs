@@ -1,14 +1,18 @@
+use super::PackageSource;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PackageKey {
     name: String,
     version: semver::Version,
+    source: PackageSource,
 }
 
 impl PackageKey {
-    pub fn new(name: impl ToString, version: semver::Version) -> Self {
+    pub fn new(name: impl ToString, version: semver::Version, source: PackageSource) -> Self {
         Self {
             name: name.to_string(),
             version,
+            source,
         }
     }
 
@@ -19,4 +23,8 @@ impl PackageKey {
     pub fn version(&self) -> &semver::Version {
         &self.version
     }
+
+    pub fn source(&self) -> &PackageSource {
+        &self.source
+    }
 }
@@ -0,0 +1,207 @@
+use std::{fmt::Display, path::PathBuf};
+
+/// The branch, tag or commit a git package was installed from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GitReference {
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+/// A git repository that a package was installed from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GitSource {
+    pub url: String,
+    pub reference: GitReference,
+    /// The commit that was installed, if known.
+    pub commit: Option<String>,
+}
+
+impl GitSource {
+    /// Get the installed commit, shortened for display.
+    pub fn short_commit(&self) -> Option<&str> {
+        self.commit
+            .as_deref()
+            .map(|commit| commit.get(..7).unwrap_or(commit))
+    }
+}
+
+/// Where an installed package came from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PackageSource {
+    /// A package registry, identified by its index URL.
+    Registry(String),
+    Git(GitSource),
+    /// A local directory.
+    Path(PathBuf),
+}
+
+impl PackageSource {
+    /// The crates.io registry.
+    pub fn crates_io() -> Self {
+        Self::Registry(crates_index::git::URL.to_string())
+    }
+
+    /// Parse the source part of a package key, like `git+https://host/repo?branch=main#commit`.
+    pub fn parse(source: &str) -> Option<Self> {
+        let (kind, location) = source.split_once('+')?;
+        match kind {
+            "registry" | "sparse" => Some(Self::Registry(location.to_string())),
+            "path" => {
+                // The location is a `file://` URL, with special characters percent-encoded
+                let path = url::Url::parse(location)
+                    .ok()
+                    .and_then(|url| url.to_file_path().ok())
+                    .unwrap_or_else(|| PathBuf::from(location));
+                Some(Self::Path(path))
+            }
+            "git" => {
+                let (location, commit) = match location.split_once('#') {
+                    Some((location, commit)) => (location, Some(commit.to_string())),
+                    None => (location, None),
+                };
+                let (url, query) = location.split_once('?').unwrap_or((location, ""));
+                // Cargo percent-encodes the reference, like `branch=feature%2Fx`
+                let reference = url::form_urlencoded::parse(query.as_bytes())
+                    .find_map(|(name, value)| match name.as_ref() {
+                        "branch" => Some(GitReference::Branch(value.into_owned())),
+                        "tag" => Some(GitReference::Tag(value.into_owned())),
+                        "rev" => Some(GitReference::Rev(value.into_owned())),
+                        _ => None,
+                    })
+                    .unwrap_or(GitReference::DefaultBranch);
+                Some(Self::Git(GitSource {
+                    url: url.to_string(),
+                    reference,
+                    commit,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse the source from a full package key, like `name version (source)`.
+    pub fn from_key(key: &str) -> Option<Self> {
+        let source = key.split_once(" (")?.1.strip_suffix(')')?;
+        Self::parse(source)
+    }
+
+    pub fn is_crates_io(&self) -> bool {
+        match self {
+            Self::Registry(url) => {
+                url == crates_index::git::URL
+                    || crates_index::sparse::URL.strip_prefix("sparse+") == Some(url.as_str())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for GitReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DefaultBranch => write!(f, "default branch"),
+            Self::Branch(branch) => write!(f, "branch {}", branch),
+            Self::Tag(tag) => write!(f, "tag {}", tag),
+            Self::Rev(rev) => write!(f, "rev {}", rev),
+        }
+    }
+}
+
+impl Display for PackageSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Registry(_) if self.is_crates_io() => write!(f, "crates.io"),
+            Self::Registry(url) => write!(f, "registry {}", url),
+            Self::Git(git) => write!(f, "git {} ({})", git.url, git.reference),
+            Self::Path(path) => write!(f, "path {}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_registry() {
+        assert_eq!(
+            PackageSource::parse("registry+https://github.com/rust-lang/crates.io-index"),
+            Some(PackageSource::crates_io())
+        );
+        let sparse = PackageSource::parse("sparse+https://index.crates.io/").unwrap();
+        assert!(sparse.is_crates_io());
+        let other = PackageSource::parse("registry+https://example.com/index").unwrap();
+        assert!(!other.is_crates_io());
+    }
+
+    #[test]
+    fn parse_git() {
+        assert_eq!(
+            PackageSource::parse("git+https://github.com/foo/bar?branch=main#0123456789abcdef"),
+            Some(PackageSource::Git(GitSource {
+                url: "https://github.com/foo/bar".to_string(),
+                reference: GitReference::Branch("main".to_string()),
+                commit: Some("0123456789abcdef".to_string()),
+            }))
+        );
+        assert_eq!(
+            PackageSource::parse("git+https://github.com/foo/bar#0123456"),
+            Some(PackageSource::Git(GitSource {
+                url: "https://github.com/foo/bar".to_string(),
+                reference: GitReference::DefaultBranch,
+                commit: Some("0123456".to_string()),
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_git_decodes_references() {
+        let reference = |source: &str| match PackageSource::parse(source) {
+            Some(PackageSource::Git(git_source)) => git_source.reference,
+            other => panic!("Not a git source: {:?}", other),
+        };
+        assert_eq!(
+            reference("git+https://host/repo?branch=feature%2Fx#abc"),
+            GitReference::Branch("feature/x".to_string())
+        );
+        assert_eq!(
+            reference("git+https://host/repo?tag=v1.0%2Bbuild#abc"),
+            GitReference::Tag("v1.0+build".to_string())
+        );
+        assert_eq!(
+            reference("git+https://host/repo?rev=abc123#abc123"),
+            GitReference::Rev("abc123".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_path_decodes_urls() {
+        assert_eq!(
+            PackageSource::parse("path+file:///home/me/my%20tool"),
+            Some(PackageSource::Path(PathBuf::from("/home/me/my tool")))
+        );
+        assert_eq!(
+            PackageSource::parse("path+file:///home/me/tool%23two"),
+            Some(PackageSource::Path(PathBuf::from("/home/me/tool#two")))
+        );
+    }
+
+    #[test]
+    fn parse_rejects_unknown_kinds() {
+        assert_eq!(PackageSource::parse("https://example.com"), None);
+        assert_eq!(PackageSource::parse("local-registry+/tmp/x"), None);
+    }
+
+    #[test]
+    fn from_key() {
+        assert_eq!(
+            PackageSource::from_key(
+                "ripgrep 14.0.3 (registry+https://github.com/rust-lang/crates.io-index)"
+            ),
+            Some(PackageSource::crates_io())
+        );
+        assert_eq!(PackageSource::from_key("ripgrep 14.0.3"), None);
+    }
+}
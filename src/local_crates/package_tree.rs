@@ -36,6 +36,7 @@ impl PackageTree {
                 Package::new(
                     package.name().to_string(),
                     package.version().clone(),
+                    package.source().clone(),
                     binaries,
                 )
             })
@@ -57,6 +58,28 @@ impl PackageTree {
             .find(|package| package.name() == package_name.as_ref())
     }
 
+    /// Keep only the packages that match a predicate.
+    pub fn retain(&mut self, predicate: impl FnMut(&Package) -> bool) {
+        self.packages.retain(predicate);
+    }
+
+    /// Reorder the packages by a key.
+    ///
+    /// The sort is stable, so packages with the same key stay in name order.
+    pub fn sort_by_key<K: Ord>(&mut self, key: impl FnMut(&Package) -> K) {
+        self.packages.sort_by_cached_key(key);
+    }
+
+    /// Find the package that owns the given binary.
+    pub fn get_by_binary(&self, binary_name: impl AsRef<str>) -> Option<&Package> {
+        self.packages.iter().find(|package| {
+            package
+                .binaries()
+                .iter()
+                .any(|binary| binary.name() == binary_name.as_ref())
+        })
+    }
+
     pub fn print(&self, formatting: PackageFormatting) {
         match formatting {
             PackageFormatting::Long => {
@@ -1,27 +1,69 @@
-use std::time::Duration;
+use std::{path::PathBuf, process::ExitCode, time::Duration};
 
-use clap::{Parser, Subcommand};
+use anyhow::Context;
+use audit::PackageAuditor;
+use clap::{CommandFactory, Parser, Subcommand};
 
+mod advisory_db;
+mod audit;
+mod build_log;
+mod build_progress;
 mod cargo;
+mod cargo_config;
+mod completions;
 mod crates;
+mod disk_usage;
+mod doctor;
+mod env_manager;
+mod error;
+mod git;
 mod helper;
+mod history;
+mod http;
+mod license_policy;
 mod local_crates;
+mod orphan_cleaner;
+mod outdated_report;
+mod package_filter;
 mod package_installer;
 mod package_updater;
+mod path_source;
+mod state;
+mod update_status;
 
-use crates::{CratesIoClient, CratesRegistry};
+use cargo::BuildOptions;
+use completions::{CompletionNames, CompletionShell};
+use crates::{date_text, CratesIoClient, CratesRegistry, DependencyResolver};
 use crates_index::DependencyKind;
-use local_crates::{Package, PackageFormatting, PackageTree};
+use disk_usage::DiskUsage;
+use doctor::Doctor;
+use error::{
+    CapError, EXIT_ADVISORIES_FOUND, EXIT_FAILURE, EXIT_LICENSE_DENIED, EXIT_SUCCESS,
+    EXIT_UPDATES_AVAILABLE,
+};
+use local_crates::{
+    GitReference, GitSource, Package, PackageFormatting, PackageSource, PackageTree,
+};
+use orphan_cleaner::{CleanupAction, OrphanCleaner};
+use outdated_report::{BumpKind, OutdatedReport, OutdatedSort};
 use owo_colors::OwoColorize;
-use package_installer::PackageInstaller;
+use package_filter::{ListSort, ListSource, PackageFilter};
+use package_installer::{PackageInstaller, UninstallTarget};
 use package_updater::PackageUpdater;
+use regex::Regex;
+use state::StatusCache;
 
 #[derive(Debug, Subcommand)]
 enum Commands {
     #[clap(name = "install", alias = "i", about = "Install a package")]
     Install {
-        #[clap(name = "package", action, help = "The package to be installed")]
-        package: String,
+        #[clap(
+            name = "package",
+            action,
+            required_unless_present = "path",
+            help = "The package to be installed"
+        )]
+        package: Option<String>,
         #[clap(short = 'l', long = "locked", action, help = "Use crate lockfile")]
         locked: bool,
         #[clap(short = 'f', long = "forced", action, help = "Force installation")]
@@ -33,6 +75,45 @@ enum Commands {
             help = "Use a nightly toolchain"
         )]
         nightly: bool,
+        #[clap(long = "git", action, help = "Install from a git repository")]
+        git: Option<String>,
+        #[clap(
+            long = "branch",
+            action,
+            requires = "git",
+            group = "git_reference",
+            help = "Git branch to install from"
+        )]
+        branch: Option<String>,
+        #[clap(
+            long = "tag",
+            action,
+            requires = "git",
+            group = "git_reference",
+            help = "Git tag to install from"
+        )]
+        tag: Option<String>,
+        #[clap(
+            long = "rev",
+            action,
+            requires = "git",
+            group = "git_reference",
+            help = "Git commit to install from"
+        )]
+        rev: Option<String>,
+        #[clap(
+            long = "path",
+            action,
+            conflicts_with = "git",
+            help = "Install from a local source dir"
+        )]
+        path: Option<PathBuf>,
+        #[clap(
+            long = "dry-run",
+            action,
+            help = "Print the cargo commands instead of running them"
+        )]
+        dry_run: bool,
     },
     #[clap(name = "update", about = "Update installed packages")]
     Update {
@@ -40,21 +121,277 @@ enum Commands {
         package: Option<String>,
         #[clap(short, long, action, help = "Use cached crates index")]
         cached: bool,
+        #[clap(short = 'l', long = "locked", action, help = "Use crate lockfile")]
+        locked: bool,
+        #[clap(
+            long = "no-locked",
+            action,
+            conflicts_with = "locked",
+            help = "Don't use crate lockfile, even if it was used before"
+        )]
+        no_locked: bool,
+        #[clap(
+            short = 'j',
+            long = "jobs",
+            action,
+            default_value_t = 1,
+            help = "Number of packages to update in parallel"
+        )]
+        jobs: usize,
+        #[clap(
+            long = "dry-run",
+            action,
+            help = "Print the cargo commands instead of running them"
+        )]
+        dry_run: bool,
     },
     #[clap(name = "check", about = "Check for updates")]
     Check {
         #[clap(name = "package", action, help = "Check a specific package")]
         package: Option<String>,
+        #[clap(
+            short = 'u',
+            long = "update-index",
+            action,
+            help = "Update the crates index first"
+        )]
+        update_index: bool,
+        #[clap(
+            long = "background",
+            action,
+            conflicts_with = "package",
+            help = "Refresh the status cache in a detached process, if it is due"
+        )]
+        background: bool,
+    },
+    #[clap(name = "outdated", about = "Show a table of out of date packages")]
+    Outdated {
+        #[clap(long = "major", action, help = "Show major updates")]
+        major: bool,
+        #[clap(long = "minor", action, help = "Show minor updates")]
+        minor: bool,
+        #[clap(long = "patch", action, help = "Show patch updates")]
+        patch: bool,
+        #[clap(
+            short = 's',
+            long = "sort",
+            value_enum,
+            default_value = "name",
+            help = "Column to sort by"
+        )]
+        sort: OutdatedSort,
+        #[clap(
+            short = 'u',
+            long = "update-index",
+            action,
+            help = "Update the crates index first"
+        )]
+        update_index: bool,
+    },
+    #[clap(
+        name = "audit",
+        about = "Check installed packages against the RustSec advisory database"
+    )]
+    Audit {
+        #[clap(long = "db", action, help = "Path of the advisory database checkout")]
+        db: Option<PathBuf>,
+        #[clap(
+            long = "deep",
+            action,
+            help = "Also check the dependencies embedded by cargo auditable"
+        )]
+        deep: bool,
+    },
+    #[clap(
+        name = "licenses",
+        about = "Show the licenses of installed packages and check them against the policy"
+    )]
+    Licenses,
+    #[clap(name = "status", about = "Show how many updates are available")]
+    Status {
+        #[clap(
+            long = "cached",
+            action,
+            help = "Only read the status cache, even if it is outdated"
+        )]
+        cached: bool,
+        #[clap(long = "short", action, help = "Print a single line for shell prompts")]
+        short: bool,
     },
-    #[clap(name = "uninstall", about = "Remove a package")]
+    #[clap(name = "uninstall", about = "Remove packages")]
     Uninstall {
-        #[clap(name = "package", action, help = "The package to be uninstalled")]
-        package: String,
+        #[clap(
+            name = "package",
+            action,
+            required = true,
+            help = "The packages to be uninstalled"
+        )]
+        packages: Vec<String>,
+        #[clap(
+            short = 'b',
+            long = "bin",
+            action,
+            help = "Treat arguments as binary names and remove the owning packages"
+        )]
+        bin: bool,
+        #[clap(
+            short = 'o',
+            long = "only",
+            action,
+            requires = "bin",
+            help = "Only remove the named binaries, keep the rest of the package"
+        )]
+        only: bool,
+        #[clap(
+            short = 'y',
+            long = "yes",
+            action,
+            help = "Skip the confirmation prompt"
+        )]
+        yes: bool,
+        #[clap(
+            long = "dry-run",
+            action,
+            help = "Print the cargo commands instead of running them"
+        )]
+        dry_run: bool,
     },
     #[clap(name = "list", about = "List installed packages")]
     List {
+        #[clap(
+            name = "pattern",
+            action,
+            help = "Only list packages whose package or binary names match this regex"
+        )]
+        pattern: Option<String>,
         #[clap(short, long, action, help = "More compact output")]
         short: bool,
+        #[clap(
+            long = "orphans",
+            action,
+            conflicts_with_all = ["pattern", "sort", "outdated", "source"],
+            help = "List untracked, missing and rustup proxy binaries instead"
+        )]
+        orphans: bool,
+        #[clap(
+            short = 't',
+            long = "times",
+            action,
+            conflicts_with = "orphans",
+            help = "Show when packages were last updated and how long they take to build"
+        )]
+        times: bool,
+        #[clap(
+            long = "sizes",
+            action,
+            conflicts_with_all = ["orphans", "times"],
+            help = "Show the size of the binaries, sorted largest first by default"
+        )]
+        sizes: bool,
+        #[clap(
+            long = "sort",
+            value_enum,
+            help = "Sort by name, most recently installed or binary size"
+        )]
+        sort: Option<ListSort>,
+        #[clap(
+            long = "outdated",
+            action,
+            help = "Only list packages that have an update available"
+        )]
+        outdated: bool,
+        #[clap(
+            long = "source",
+            value_enum,
+            help = "Only list packages installed from this kind of source"
+        )]
+        source: Option<ListSource>,
+    },
+    #[clap(
+        name = "du",
+        about = "Show the disk space used by packages, including cargo's caches"
+    )]
+    Du,
+    #[clap(name = "cleanup", about = "Clean up orphaned binaries")]
+    Cleanup {
+        #[clap(name = "binary", action, help = "Only clean up specific binaries")]
+        binaries: Vec<String>,
+        #[clap(
+            long = "adopt",
+            action,
+            group = "action",
+            help = "Reinstall the owning packages"
+        )]
+        adopt: bool,
+        #[clap(
+            long = "ignore",
+            action,
+            group = "action",
+            help = "Leave the binaries alone in the future"
+        )]
+        ignore: bool,
+        #[clap(
+            long = "delete",
+            action,
+            group = "action",
+            help = "Delete untracked files and forget missing binaries"
+        )]
+        delete: bool,
+        #[clap(
+            short = 'p',
+            long = "package",
+            action,
+            help = "Package that untracked binaries belong to, when adopting"
+        )]
+        package: Option<String>,
+        #[clap(
+            long = "dry-run",
+            action,
+            help = "Print the cargo commands instead of running them"
+        )]
+        dry_run: bool,
+    },
+    #[clap(name = "logs", about = "Show build logs of a package")]
+    Logs {
+        #[clap(name = "package", action, help = "Package name")]
+        package: String,
+        #[clap(short, long, action, help = "List all build logs")]
+        list: bool,
+        #[clap(
+            short = 'n',
+            long = "attempt",
+            action,
+            default_value_t = 1,
+            help = "Show an older log, 1 being the most recent"
+        )]
+        attempt: usize,
+    },
+    #[clap(
+        name = "history",
+        about = "Show the install, update and uninstall history"
+    )]
+    History {
+        #[clap(
+            name = "package",
+            action,
+            help = "Only show the history of this package"
+        )]
+        package: Option<String>,
+    },
+    #[clap(name = "doctor", about = "Check the environment for problems")]
+    Doctor {
+        #[clap(long = "offline", action, help = "Skip network checks")]
+        offline: bool,
+    },
+    #[clap(name = "index", about = "Manage the crates.io index")]
+    Index {
+        #[clap(subcommand)]
+        command: IndexCommands,
+    },
+    #[clap(name = "env", about = "Manage named environments")]
+    Env {
+        #[clap(subcommand)]
+        command: EnvCommands,
     },
     #[clap(name = "search", about = "Search for packages")]
     Search {
@@ -65,6 +402,120 @@ enum Commands {
     Info {
         #[clap(name = "package", action, help = "Package name")]
         package: String,
+        #[clap(
+            long = "tree",
+            action,
+            help = "Show the resolved dependency tree instead"
+        )]
+        tree: bool,
+        #[clap(
+            long = "version",
+            action,
+            requires = "tree",
+            help = "Version to resolve, defaults to the latest one"
+        )]
+        version: Option<semver::Version>,
+        #[clap(
+            short = 'F',
+            long = "features",
+            action,
+            value_delimiter = ',',
+            requires = "tree",
+            help = "Features to resolve with"
+        )]
+        features: Vec<String>,
+        #[clap(
+            long = "all-features",
+            action,
+            requires = "tree",
+            help = "Resolve with all features"
+        )]
+        all_features: bool,
+        #[clap(
+            long = "no-default-features",
+            action,
+            requires = "tree",
+            help = "Resolve without the default features"
+        )]
+        no_default_features: bool,
+    },
+    #[clap(name = "completions", about = "Print a shell completion script")]
+    Completions {
+        #[clap(name = "shell", value_enum, help = "The shell to complete in")]
+        shell: CompletionShell,
+    },
+    #[clap(name = "__complete", hide = true)]
+    Complete {
+        #[clap(name = "names", value_enum)]
+        names: CompletionNames,
+        #[clap(name = "prefix", action, default_value = "")]
+        prefix: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum IndexCommands {
+    #[clap(
+        name = "repair",
+        about = "Move a broken index aside and fetch it again"
+    )]
+    Repair {
+        #[clap(
+            short = 'f',
+            long = "forced",
+            action,
+            help = "Repair even if the index looks healthy"
+        )]
+        forced: bool,
+        #[clap(
+            short = 'y',
+            long = "yes",
+            action,
+            help = "Skip the confirmation prompt"
+        )]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum EnvCommands {
+    #[clap(
+        name = "create",
+        about = "Create an environment with its own install root"
+    )]
+    Create {
+        #[clap(name = "name", action, help = "Environment name")]
+        name: String,
+        #[clap(
+            short = 't',
+            long = "toolchain",
+            action,
+            help = "Toolchain to build packages with"
+        )]
+        toolchain: Option<String>,
+    },
+    #[clap(name = "use", about = "Manage packages in an environment")]
+    Use {
+        #[clap(
+            name = "name",
+            action,
+            help = "Environment name, or default for the regular install root"
+        )]
+        name: String,
+    },
+    #[clap(name = "list", about = "List environments")]
+    List,
+    #[clap(name = "pin", about = "Pin a package in the active environment")]
+    Pin {
+        #[clap(name = "package", action, help = "Package name")]
+        package: String,
+        #[clap(name = "requirement", action, help = "Version requirement, like ~1.2")]
+        requirement: String,
+    },
+    #[clap(name = "unpin", about = "Unpin a package in the active environment")]
+    Unpin {
+        #[clap(name = "package", action, help = "Package name")]
+        package: String,
     },
 }
 
@@ -74,11 +525,48 @@ enum Commands {
 struct App {
     #[clap(subcommand)]
     command: Commands,
+    #[clap(
+        long = "root",
+        global = true,
+        action,
+        help = "Manage packages in this install root instead of the cargo home dir"
+    )]
+    root: Option<PathBuf>,
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
     let app = App::parse();
-    let mut registry = CratesRegistry::new();
+
+    // Cargo and cap both pick up the install root from the environment
+    if let Some(root) = &app.root {
+        std::env::set_var("CARGO_INSTALL_ROOT", root);
+    }
+
+    match run(app) {
+        Ok(exit_code) => ExitCode::from(exit_code),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            let exit_code = err
+                .downcast_ref::<CapError>()
+                .map(CapError::exit_code)
+                .unwrap_or(EXIT_FAILURE);
+            ExitCode::from(exit_code)
+        }
+    }
+}
+
+fn run(app: App) -> anyhow::Result<u8> {
+    // Git fetches the index and git packages, and reads cargo's HTTP settings from the environment.
+    // Broken settings must not stop commands that work offline, so they are only warned about.
+    match http::HttpConfig::load() {
+        Ok(config) => config.export_to_git_env(),
+        Err(_) if matches!(app.command, Commands::Complete { .. }) => (),
+        Err(err) => eprintln!(
+            "{} Ignoring the HTTP settings of cargo: {:#}",
+            "Warning:".yellow(),
+            err
+        ),
+    }
 
     match app.command {
         Commands::Install {
@@ -86,53 +574,224 @@ fn main() -> anyhow::Result<()> {
             locked,
             forced,
             nightly,
+            git,
+            branch,
+            tag,
+            rev,
+            path,
+            dry_run,
         } => {
+            let source = if let Some(url) = git {
+                let reference = match (branch, tag, rev) {
+                    (Some(branch), _, _) => GitReference::Branch(branch),
+                    (_, Some(tag), _) => GitReference::Tag(tag),
+                    (_, _, Some(rev)) => GitReference::Rev(rev),
+                    _ => GitReference::DefaultBranch,
+                };
+                Some(PackageSource::Git(GitSource {
+                    url,
+                    reference,
+                    commit: None,
+                }))
+            } else if let Some(path) = path {
+                let path = path
+                    .canonicalize()
+                    .with_context(|| format!("Unable to find source dir {}.", path.display()))?;
+                Some(PackageSource::Path(path))
+            } else {
+                None
+            };
+
+            // Path packages are named by their manifest, unless a name is given
+            let package = match (package, &source) {
+                (Some(package), _) => package,
+                (None, Some(PackageSource::Path(path))) => path_source::manifest_name(path)?,
+                (None, _) => unreachable!("clap requires a package name without --path"),
+            };
+
+            let registry = CratesRegistry::new()?;
             let packages = PackageTree::build()?;
             let installer = PackageInstaller::new(&registry, &packages);
-            installer.install_package(package, locked, forced, nightly)?;
+            installer.install_package(package, locked, forced, nightly, source, dry_run)?;
         }
 
-        Commands::Uninstall { package } => {
+        Commands::Uninstall {
+            packages: names,
+            bin,
+            only,
+            yes,
+            dry_run,
+        } => {
+            let target = match (bin, only) {
+                (_, true) => UninstallTarget::SingleBinary,
+                (true, false) => UninstallTarget::Binary,
+                (false, false) => UninstallTarget::Package,
+            };
+            let registry = CratesRegistry::new()?;
             let packages = PackageTree::build()?;
             let installer = PackageInstaller::new(&registry, &packages);
-            installer.uninstall_package(package)?;
+            installer.uninstall_packages(&names, target, yes, dry_run)?;
         }
 
-        Commands::Check { package } => {
-            let packages = PackageTree::build()?;
-            let updater = PackageUpdater::new(&registry, &packages);
+        Commands::Check {
+            package,
+            update_index,
+            background,
+        } => {
+            if background {
+                update_status::refresh_in_background()?;
+                return Ok(EXIT_SUCCESS);
+            }
 
-            if let Some(target_package) = package {
-                updater.check_package(target_package)?;
+            let has_updates = if let Some(target_package) = package {
+                let mut registry = CratesRegistry::new()?;
+                if update_index {
+                    registry.update_index_with_progress()?;
+                }
+                let packages = PackageTree::build()?;
+                let updater = PackageUpdater::new(&registry, &packages);
+                updater.check_package(target_package)?.is_out_of_date()
             } else {
-                updater.check_all_packages()?;
+                update_status::check_all_packages(update_index)?
+            };
+            if has_updates {
+                return Ok(EXIT_UPDATES_AVAILABLE);
+            }
+        }
+
+        Commands::Outdated {
+            major,
+            minor,
+            patch,
+            sort,
+            update_index,
+        } => {
+            let mut registry = CratesRegistry::new()?;
+            if update_index {
+                registry.update_index_with_progress()?;
+            }
+            let packages = PackageTree::build()?;
+            let bumps = [
+                (major, BumpKind::Major),
+                (minor, BumpKind::Minor),
+                (patch, BumpKind::Patch),
+            ]
+            .into_iter()
+            .filter_map(|(is_shown, bump)| is_shown.then_some(bump))
+            .collect::<Vec<_>>();
+            if OutdatedReport::new(&registry, &packages).print(&bumps, sort)? {
+                return Ok(EXIT_UPDATES_AVAILABLE);
+            }
+        }
+
+        Commands::Audit { db, deep } => {
+            let registry = CratesRegistry::new()?;
+            let packages = PackageTree::build()?;
+            if PackageAuditor::new(&registry, &packages).audit(db, deep)? {
+                return Ok(EXIT_ADVISORIES_FOUND);
+            }
+        }
+
+        Commands::Status { cached, short } => {
+            let cache = StatusCache::load()?;
+            let is_due = match &cache {
+                Some(cache) => update_status::is_stale(cache)?,
+                None => true,
+            };
+            // Prompts must not wait for a check, they get the result next time
+            if is_due && !cached && short {
+                update_status::refresh_in_background()?;
+            } else if is_due && !cached {
+                // The check prints its result, which is the same as the cached one
+                if update_status::check_all_packages(false)? {
+                    return Ok(EXIT_UPDATES_AVAILABLE);
+                }
+                return Ok(EXIT_SUCCESS);
+            }
+
+            let Some(cache) = cache else {
+                if !short {
+                    eprintln!("No update status cached yet. Run cap check first.");
+                }
+                return Ok(EXIT_SUCCESS);
+            };
+            update_status::print_status(&cache, short);
+            if !cache.outdated.is_empty() {
+                return Ok(EXIT_UPDATES_AVAILABLE);
             }
         }
 
         Commands::Update {
             package: specific_package,
             cached,
+            locked,
+            no_locked,
+            jobs,
+            dry_run,
         } => {
+            let mut registry = CratesRegistry::new()?;
             if !cached {
-                let progress_bar = indicatif::ProgressBar::new_spinner();
-                progress_bar.set_message("Updating crates index...");
-                progress_bar.enable_steady_tick(Duration::from_millis(100));
-                registry._update_index()?;
-                progress_bar.finish_and_clear();
+                registry.update_index_with_progress()?;
             }
 
+            // Without either flag, packages are built the way they were last time
+            let locked = match (locked, no_locked) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+
             let packages = PackageTree::build()?;
             let updater = PackageUpdater::new(&registry, &packages);
 
             if let Some(target_package) = specific_package {
-                updater.update_package(target_package)?;
+                updater.update_package(target_package, locked, dry_run)?;
             } else {
-                updater.update_all_packages()?;
+                updater.update_all_packages(jobs, locked, dry_run)?;
             }
         }
 
-        Commands::List { short } => {
-            let packages = PackageTree::build()?;
+        Commands::List {
+            pattern,
+            short,
+            orphans,
+            times,
+            sizes,
+            sort,
+            outdated,
+            source,
+        } => {
+            let mut packages = PackageTree::build()?;
+            if orphans {
+                OrphanCleaner::print_orphans(&packages)?;
+                return Ok(EXIT_SUCCESS);
+            }
+
+            let filter = PackageFilter {
+                pattern: pattern.as_deref().map(Regex::new).transpose()?,
+                source,
+                outdated,
+            };
+            filter.apply(&mut packages)?;
+            if packages.packages().next().is_none() && !filter.is_empty() {
+                println!("No installed packages match.");
+                return Ok(EXIT_SUCCESS);
+            }
+            let default_sort = if sizes {
+                ListSort::Size
+            } else {
+                ListSort::Name
+            };
+            package_filter::sort_packages(&mut packages, sort.unwrap_or(default_sort))?;
+
+            if times {
+                history::print_package_times(&packages, short)?;
+                return Ok(EXIT_SUCCESS);
+            }
+            if sizes {
+                DiskUsage::new(&packages).print_binary_sizes(short)?;
+                return Ok(EXIT_SUCCESS);
+            }
             let formatting = if short {
                 PackageFormatting::Short
             } else {
@@ -141,7 +800,75 @@ fn main() -> anyhow::Result<()> {
             packages.print(formatting);
         }
 
+        Commands::Licenses => {
+            let packages = PackageTree::build()?;
+            if license_policy::print_licenses(&packages)? {
+                return Ok(EXIT_LICENSE_DENIED);
+            }
+        }
+
+        Commands::Du => {
+            let packages = PackageTree::build()?;
+            DiskUsage::new(&packages).print_report()?;
+        }
+
+        Commands::Cleanup {
+            binaries,
+            adopt,
+            ignore,
+            delete,
+            package,
+            dry_run,
+        } => {
+            let action = match (adopt, ignore, delete) {
+                (true, _, _) => Some(CleanupAction::Adopt),
+                (_, true, _) => Some(CleanupAction::Ignore),
+                (_, _, true) => Some(CleanupAction::Delete),
+                _ => None,
+            };
+            let registry = CratesRegistry::new()?;
+            let packages = PackageTree::build()?;
+            let cleaner = OrphanCleaner::new(&registry, &packages);
+            cleaner.cleanup(&binaries, action, package.as_deref(), dry_run)?;
+        }
+
+        Commands::Logs {
+            package,
+            list,
+            attempt,
+        } => {
+            build_log::show_logs(&package, list, attempt)?;
+        }
+
+        Commands::History { package } => {
+            history::show_history(package.as_deref())?;
+        }
+
+        Commands::Doctor { offline } => {
+            let packages = PackageTree::build()?;
+            let doctor = Doctor::new(&packages, offline);
+            if !doctor.run()? {
+                return Ok(EXIT_FAILURE);
+            }
+        }
+
+        Commands::Index { command } => match command {
+            IndexCommands::Repair { forced, yes } => CratesRegistry::repair_index(forced, yes)?,
+        },
+
+        Commands::Env { command } => match command {
+            EnvCommands::Create { name, toolchain } => env_manager::create_env(&name, toolchain)?,
+            EnvCommands::Use { name } => env_manager::use_env(&name)?,
+            EnvCommands::List => env_manager::list_envs()?,
+            EnvCommands::Pin {
+                package,
+                requirement,
+            } => env_manager::pin_package(&package, &requirement)?,
+            EnvCommands::Unpin { package } => env_manager::unpin_package(&package)?,
+        },
+
         Commands::Search { package } => {
+            let registry = CratesRegistry::new()?;
             let progress_bar = indicatif::ProgressBar::new_spinner();
             progress_bar.set_message("Searching for packages...");
             progress_bar.enable_steady_tick(Duration::from_millis(100));
@@ -177,8 +904,33 @@ fn main() -> anyhow::Result<()> {
             }
         }
 
-        Commands::Info { package } => {
-            let mut client = CratesIoClient::new();
+        Commands::Info {
+            package,
+            tree,
+            version,
+            features,
+            all_features,
+            no_default_features,
+        } => {
+            let registry = CratesRegistry::new()?;
+            if tree {
+                let options = BuildOptions {
+                    features: features.into_iter().collect(),
+                    all_features,
+                    no_default_features,
+                    ..Default::default()
+                };
+                let graph = DependencyResolver::new(&registry).resolve(
+                    &package,
+                    version.as_ref(),
+                    &options,
+                )?;
+                graph.print_tree();
+                graph.print_summary();
+                return Ok(EXIT_SUCCESS);
+            }
+
+            let mut client = CratesIoClient::new()?;
             let progress_bar = indicatif::ProgressBar::new_spinner();
             progress_bar.set_message("Fetching package info...");
             progress_bar.enable_steady_tick(Duration::from_millis(100));
@@ -187,22 +939,31 @@ fn main() -> anyhow::Result<()> {
             progress_bar.finish_and_clear();
 
             let Some(highest_version) = package_1.highest_normal_version() else {
-                println!("Unable to find any suitable version for package {}.", package_1.name().blue());
-                return Ok(())
+                println!(
+                    "Unable to find any suitable version for package {}.",
+                    package_1.name().blue()
+                );
+                return Ok(EXIT_SUCCESS);
             };
 
-            // Print package name and version
+            let highest_version_info = package_2.version(highest_version.version());
+
+            // Print package name, version and publish date
+            let published_text = highest_version_info
+                .map(|version| format!(" (published {})", date_text(&version.created_at)))
+                .unwrap_or_default();
             println!(
-                "{} {}",
+                "{} {}{}",
                 highest_version.name(),
-                highest_version.version().bright_black()
+                highest_version.version().bright_black(),
+                published_text.bright_black()
             );
 
             // Print description
-            if let Some(description) = package_2.description {
+            if let Some(description) = &package_2.metadata.description {
                 if let Ok((terminal_width, _)) = termion::terminal_size() {
                     let description =
-                        textwrap::fill(&description, terminal_width.saturating_sub(4) as usize);
+                        textwrap::fill(description, terminal_width.saturating_sub(4) as usize);
                     println!("  Description");
                     println!(
                         "    {}",
@@ -215,6 +976,45 @@ fn main() -> anyhow::Result<()> {
                 }
             }
 
+            // Print links and license
+            let metadata = &package_2.metadata;
+            let details = [
+                (
+                    "License",
+                    highest_version_info.and_then(|version| version.license.clone()),
+                ),
+                ("Homepage", metadata.homepage.clone()),
+                ("Repository", metadata.repository.clone()),
+                ("Documentation", metadata.documentation.clone()),
+            ];
+            for (label, value) in details {
+                if let Some(value) = value {
+                    println!("  {:<14}{}", label, value.bright_black());
+                }
+            }
+
+            // Print downloads and dates
+            let recent_downloads_text = metadata
+                .recent_downloads
+                .map(|downloads| format!(" ({} recently)", downloads))
+                .unwrap_or_default();
+            println!(
+                "  {:<14}{}{}",
+                "Downloads",
+                metadata.downloads.cyan(),
+                recent_downloads_text.bright_black()
+            );
+            println!(
+                "  {:<14}{}",
+                "Created",
+                date_text(&metadata.created_at).bright_black()
+            );
+            println!(
+                "  {:<14}{}",
+                "Updated",
+                date_text(&metadata.updated_at).bright_black()
+            );
+
             let yanked_version_count = package_1
                 .versions()
                 .iter()
@@ -228,6 +1028,16 @@ fn main() -> anyhow::Result<()> {
                 yanked_version_count.cyan()
             );
 
+            // Print the most recent versions with their publish dates
+            for version in package_2.versions.iter().take(5) {
+                println!(
+                    "    {} {}{}",
+                    version.num,
+                    date_text(&version.created_at).bright_black(),
+                    if version.yanked { " (yanked)" } else { "" }.yellow()
+                );
+            }
+
             // Print dependency count
             println!(
                 "  {} {}",
@@ -293,7 +1103,15 @@ fn main() -> anyhow::Result<()> {
                 );
             }
         }
+
+        Commands::Completions { shell } => {
+            completions::print_completions(shell, &mut App::command())?;
+        }
+
+        Commands::Complete { names, prefix } => {
+            completions::print_names(names, &prefix)?;
+        }
     }
 
-    Ok(())
+    Ok(EXIT_SUCCESS)
 }
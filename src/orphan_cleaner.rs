@@ -0,0 +1,360 @@
+use std::fs::File;
+
+use indicatif::MultiProgress;
+use owo_colors::OwoColorize;
+use semver::Version;
+
+use crate::{
+    cargo::BuildOptions,
+    local_crates::{BinaryInvocationScraper, Orphan, OrphanKind, OrphanScanner, PackageSource},
+    state::CapState,
+    CratesRegistry, PackageTree,
+};
+
+/// What to do with an orphaned binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupAction {
+    /// Reinstall the owning package, so cargo tracks the binary again.
+    Adopt,
+    /// Remember the binary and leave it alone in the future.
+    Ignore,
+    /// Delete the file, or drop the binary from cargo's metadata if the file is missing.
+    Delete,
+    /// Do nothing this time.
+    Skip,
+}
+
+pub struct OrphanCleaner<'a> {
+    registry: &'a CratesRegistry,
+    packages: &'a PackageTree,
+}
+
+impl<'a> OrphanCleaner<'a> {
+    pub fn new(registry: &'a CratesRegistry, packages: &'a PackageTree) -> Self {
+        Self { registry, packages }
+    }
+
+    pub fn print_orphans(packages: &PackageTree) -> anyhow::Result<()> {
+        let orphans = OrphanScanner::scan(packages)?;
+        let state = CapState::load()?;
+
+        if orphans.is_empty() {
+            println!("No {} binaries found.", "orphaned".green());
+            return Ok(());
+        }
+
+        let sections = [
+            (
+                OrphanKind::Untracked,
+                "Untracked binaries (not owned by any package)",
+            ),
+            (
+                OrphanKind::Missing,
+                "Missing binaries (tracked, but not in the bin dir)",
+            ),
+            (OrphanKind::RustupProxy, "Rustup proxies"),
+        ];
+
+        for (kind, title) in sections {
+            let orphans = orphans
+                .iter()
+                .filter(|orphan| orphan.kind() == kind)
+                .collect::<Vec<_>>();
+            if orphans.is_empty() {
+                continue;
+            }
+            println!("{}", title);
+            for orphan in orphans {
+                let owner_text = match orphan.package() {
+                    Some((name, version)) => format!(" ({} {})", name, version),
+                    None => String::default(),
+                };
+                let ignored_text = if state.ignored_binaries.contains(orphan.binary()) {
+                    " (ignored)"
+                } else {
+                    ""
+                };
+                println!(
+                    "  {}{}{}",
+                    orphan.binary().blue(),
+                    owner_text.bright_black(),
+                    ignored_text.bright_black()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clean up orphaned binaries.
+    ///
+    /// If `binaries` is empty, all orphans that aren't ignored are processed.
+    /// If `action` is `None`, the user is asked what to do with each orphan.
+    /// Untracked binaries are adopted as part of `package_name` if given.
+    /// With `dry_run`, the planned changes are printed but not carried out.
+    pub fn cleanup(
+        &self,
+        binaries: &[String],
+        action: Option<CleanupAction>,
+        package_name: Option<&str>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let state = CapState::load()?;
+
+        // Rustup proxies are never touched, ignored binaries only when named explicitly.
+        let orphans = OrphanScanner::scan(self.packages)?
+            .into_iter()
+            .filter(|orphan| orphan.kind() != OrphanKind::RustupProxy)
+            .filter(|orphan| {
+                if binaries.is_empty() {
+                    !state.ignored_binaries.contains(orphan.binary())
+                } else {
+                    binaries.iter().any(|binary| binary == orphan.binary())
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if orphans.is_empty() {
+            println!("No {} binaries to clean up.", "orphaned".green());
+            return Ok(());
+        }
+
+        if action.is_none() && !crate::helper::is_interactive() {
+            anyhow::bail!("No cleanup action given. Pass --adopt, --ignore or --delete.");
+        }
+
+        for orphan in &orphans {
+            let action = match action {
+                Some(action) => action,
+                None => Self::ask_action(orphan)?,
+            };
+            match action {
+                CleanupAction::Adopt => self.adopt(orphan, package_name, dry_run)?,
+                CleanupAction::Ignore if dry_run => {
+                    println!("{} {}.", "Would ignore".cyan(), orphan.binary().blue());
+                }
+                CleanupAction::Ignore => {
+                    // Saved right away, so later failures don't lose the choice
+                    CapState::modify(|state| {
+                        state.ignored_binaries.insert(orphan.binary().to_string());
+                    })?;
+                    println!("{} {}.", "Ignoring".cyan(), orphan.binary().blue());
+                }
+                CleanupAction::Delete => self.delete(orphan, dry_run)?,
+                CleanupAction::Skip => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ask_action(orphan: &Orphan) -> anyhow::Result<CleanupAction> {
+        let kind_text = match orphan.kind() {
+            OrphanKind::Untracked => "untracked",
+            OrphanKind::Missing => "missing",
+            OrphanKind::RustupProxy => "rustup proxy",
+        };
+        loop {
+            let answer = crate::helper::ask(format!(
+                "{} ({}): [a]dopt, [i]gnore, [d]elete, [s]kip?",
+                orphan.binary().blue(),
+                kind_text.yellow()
+            ))?;
+            match answer.to_lowercase().as_str() {
+                "a" | "adopt" => return Ok(CleanupAction::Adopt),
+                "i" | "ignore" => return Ok(CleanupAction::Ignore),
+                "d" | "delete" => return Ok(CleanupAction::Delete),
+                "s" | "skip" | "" => return Ok(CleanupAction::Skip),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Find the package an untracked binary belongs to.
+    ///
+    /// The name a binary reports for `--version` is only a guess, since the
+    /// binaries of multi-binary crates report their own name. A guess is only
+    /// used once the user confirms it, otherwise the package has to be given.
+    fn identify_untracked(
+        &self,
+        orphan: &Orphan,
+        package_name: Option<&str>,
+    ) -> anyhow::Result<Option<(String, Version)>> {
+        let guess = BinaryInvocationScraper::identify(orphan.path());
+
+        if let Some(package_name) = package_name {
+            let version = match guess {
+                Some(package_key) if package_key.name() == package_name => {
+                    package_key.version().clone()
+                }
+                _ => self.registry.get_latest_version(package_name)?,
+            };
+            return Ok(Some((package_name.to_string(), version)));
+        }
+
+        let Some(package_key) = guess else {
+            println!(
+                "Unable to identify the package of {}. Pass {} to adopt it.",
+                orphan.binary().blue(),
+                "--package <name>".bright_black()
+            );
+            return Ok(None);
+        };
+        if !crate::helper::is_interactive() {
+            println!(
+                "{} looks like package {} {}. Pass {} to adopt it.",
+                orphan.binary().blue(),
+                package_key.name().blue(),
+                package_key.version().bright_black(),
+                format!("--package {}", package_key.name()).bright_black()
+            );
+            return Ok(None);
+        }
+        let confirmed = crate::helper::confirm(format!(
+            "Adopt {} as part of package {} {}?",
+            orphan.binary().blue(),
+            package_key.name().blue(),
+            package_key.version().bright_black()
+        ))?;
+        if !confirmed {
+            return Ok(None);
+        }
+        Ok(Some((
+            package_key.name().to_string(),
+            package_key.version().clone(),
+        )))
+    }
+
+    fn adopt(
+        &self,
+        orphan: &Orphan,
+        package_name: Option<&str>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        // Missing binaries already know their package, untracked ones have to be identified.
+        let (package_name, version) = match orphan.package() {
+            Some((name, version)) => (name.to_string(), version.clone()),
+            None => match self.identify_untracked(orphan, package_name)? {
+                Some(package) => package,
+                None => return Ok(()),
+            },
+        };
+
+        // Tracked packages are reinstalled from where they came from
+        let source = self
+            .packages
+            .get(&package_name)
+            .map(|package| package.source().clone())
+            .unwrap_or_else(PackageSource::crates_io);
+        let options = BuildOptions::recorded(&package_name);
+        let progress = MultiProgress::new();
+        match &source {
+            PackageSource::Git(git_source) => crate::cargo::install_git_package(
+                &package_name,
+                git_source,
+                None,
+                true,
+                &options,
+                &progress,
+                dry_run,
+            )?,
+            PackageSource::Path(source_dir) => {
+                let source_hash = crate::path_source::source_hash(source_dir)?;
+                crate::cargo::install_path_package(
+                    &package_name,
+                    source_dir,
+                    true,
+                    &options,
+                    &progress,
+                    dry_run,
+                )?;
+                if !dry_run {
+                    let source_hash_key = crate::path_source::source_hash_key(&package_name)?;
+                    CapState::modify(|state| {
+                        state.source_hashes.insert(source_hash_key, source_hash);
+                    })?;
+                }
+            }
+            PackageSource::Registry(_) if source.is_crates_io() => {
+                let version_exists = self
+                    .registry
+                    .get_crate(&package_name)
+                    .map(|crate_| {
+                        crate_
+                            .versions()
+                            .iter()
+                            .any(|v| v.version() == version.to_string())
+                    })
+                    .unwrap_or_default();
+                if !version_exists {
+                    println!(
+                        "Package {} {} is {}.",
+                        package_name.blue(),
+                        version.bright_black(),
+                        "not available on crates.io".red()
+                    );
+                    return Ok(());
+                }
+                crate::cargo::reinstall_package(
+                    &package_name,
+                    version.clone(),
+                    &options,
+                    &progress,
+                    dry_run,
+                )?;
+            }
+            PackageSource::Registry(_) => {
+                println!(
+                    "Package {} is from {}, which cap {}.",
+                    package_name.blue(),
+                    source.bright_black(),
+                    "can't reinstall from".red()
+                );
+                return Ok(());
+            }
+        }
+        if dry_run {
+            return Ok(());
+        }
+        println!(
+            "{} {} as part of {} {}.",
+            "Adopted".green(),
+            orphan.binary().blue(),
+            package_name.blue(),
+            version.bright_black()
+        );
+
+        Ok(())
+    }
+
+    fn delete(&self, orphan: &Orphan, dry_run: bool) -> anyhow::Result<()> {
+        match orphan.package() {
+            None if dry_run => {
+                println!("{} {}.", "Would delete".cyan(), orphan.path().display());
+                return Ok(());
+            }
+            None => std::fs::remove_file(orphan.path())?,
+            Some((package_name, _)) => {
+                // Cargo fails to uninstall binaries whose file is gone,
+                // so put an empty placeholder in place for it to remove.
+                if !dry_run {
+                    File::create(orphan.path())?;
+                }
+                let result =
+                    crate::cargo::uninstall_package(package_name, &[orphan.binary()], dry_run);
+                if let Err(err) = result {
+                    // An empty file must not stay behind as a fake binary
+                    if !dry_run {
+                        let _ = std::fs::remove_file(orphan.path());
+                    }
+                    return Err(err);
+                }
+                if dry_run {
+                    return Ok(());
+                }
+            }
+        }
+        println!("{} {}.", "Deleted".red(), orphan.binary().blue());
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use clap::ValueEnum;
+use owo_colors::OwoColorize;
+use semver::{Version, VersionReq};
+
+use crate::{
+    local_crates::PackageSource,
+    package_updater::{installed_text, PackageUpdater},
+    state::Environment,
+    CratesRegistry, Package, PackageTree,
+};
+
+/// How far an update moves a package.
+///
+/// Updates that break semver compatibility count as major,
+/// so going from 0.12 to 0.13 is a major bump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum BumpKind {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl BumpKind {
+    fn between(installed: &Version, latest: &Version) -> Self {
+        let is_compatible = VersionReq::parse(&format!("^{}", installed))
+            .is_ok_and(|requirement| requirement.matches(latest));
+        if !is_compatible {
+            Self::Major
+        } else if installed.minor != latest.minor {
+            Self::Minor
+        } else {
+            Self::Patch
+        }
+    }
+}
+
+impl Display for BumpKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Major => write!(f, "major"),
+            Self::Minor => write!(f, "minor"),
+            Self::Patch => write!(f, "patch"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutdatedSort {
+    Name,
+    Bump,
+    Source,
+}
+
+/// A row of the outdated report.
+struct OutdatedRow {
+    name: String,
+    installed: String,
+    /// The latest version within the pin or the semver range of the installed version.
+    compatible: Option<String>,
+    latest: String,
+    bump: Option<BumpKind>,
+    /// Whether the installed version was yanked.
+    yanked: bool,
+    /// The minimum supported Rust version of the latest version.
+    msrv: Option<String>,
+    source: String,
+}
+
+pub struct OutdatedReport<'a> {
+    registry: &'a CratesRegistry,
+    packages: &'a PackageTree,
+}
+
+impl<'a> OutdatedReport<'a> {
+    pub fn new(registry: &'a CratesRegistry, packages: &'a PackageTree) -> Self {
+        Self { registry, packages }
+    }
+
+    /// Print a table of all out of date packages.
+    ///
+    /// Packages whose installed version was yanked are listed as well.
+    /// With any of the bump kinds given, only packages with those bumps are listed.
+    ///
+    /// Returns `true` if any package was listed.
+    pub fn print(&self, bumps: &[BumpKind], sort: OutdatedSort) -> anyhow::Result<bool> {
+        let pins = Environment::active_pins()?;
+        let updater = PackageUpdater::new(self.registry, self.packages);
+
+        let mut rows = Vec::new();
+        for package in self.packages.packages() {
+            let row = match package.source() {
+                source @ PackageSource::Registry(_) if source.is_crates_io() => {
+                    self.registry_row(package, &pins)
+                }
+                _ => updater.find_update(package, &pins).map(|update| {
+                    update.map(|update| OutdatedRow {
+                        name: package.name().to_string(),
+                        installed: installed_text(package),
+                        compatible: None,
+                        latest: update.to_string(),
+                        bump: None,
+                        yanked: false,
+                        msrv: None,
+                        source: package.source().to_string(),
+                    })
+                }),
+            };
+            match row {
+                Ok(Some(row)) => rows.push(row),
+                Ok(None) => (),
+                Err(err) => eprintln!(
+                    "{} to check package {}: {}",
+                    "Failed".yellow(),
+                    package.name().blue(),
+                    err
+                ),
+            }
+        }
+
+        if !bumps.is_empty() {
+            rows.retain(|row| row.bump.is_some_and(|bump| bumps.contains(&bump)));
+        }
+        if rows.is_empty() {
+            println!("All packages are {}.", "up to date".green());
+            return Ok(false);
+        }
+
+        match sort {
+            OutdatedSort::Name => rows.sort_by(|a, b| a.name.cmp(&b.name)),
+            OutdatedSort::Bump => rows.sort_by_key(|row| (row.bump.is_none(), row.bump)),
+            OutdatedSort::Source => rows.sort_by(|a, b| a.source.cmp(&b.source)),
+        }
+
+        Self::print_table(&rows);
+        Ok(true)
+    }
+
+    fn registry_row(
+        &self,
+        package: &Package,
+        pins: &BTreeMap<String, VersionReq>,
+    ) -> anyhow::Result<Option<OutdatedRow>> {
+        let crate_ = self.registry.get_crate(package.name())?;
+        let installed = package.version();
+
+        let versions = crate_
+            .versions()
+            .iter()
+            .filter(|version| !version.is_yanked())
+            .filter_map(|version| Some((Version::parse(version.version()).ok()?, version)))
+            .collect::<Vec<_>>();
+        let Some((latest, latest_entry)) = versions
+            .iter()
+            .filter(|(version, _)| version.pre.is_empty())
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+        else {
+            return Ok(None);
+        };
+
+        let yanked = crate_
+            .versions()
+            .iter()
+            .any(|version| version.is_yanked() && version.version() == installed.to_string());
+        if latest <= installed && !yanked {
+            return Ok(None);
+        }
+
+        let requirement = match pins.get(package.name()) {
+            Some(requirement) => requirement.clone(),
+            None => VersionReq::parse(&format!("^{}", installed))?,
+        };
+        let compatible = versions
+            .iter()
+            .map(|(version, _)| version)
+            .filter(|version| requirement.matches(version) && *version > installed)
+            .max();
+
+        Ok(Some(OutdatedRow {
+            name: package.name().to_string(),
+            installed: installed.to_string(),
+            compatible: compatible.map(Version::to_string),
+            latest: latest.to_string(),
+            bump: (latest > installed).then(|| BumpKind::between(installed, latest)),
+            yanked,
+            msrv: latest_entry.rust_version().map(str::to_string),
+            source: package.source().to_string(),
+        }))
+    }
+
+    fn print_table(rows: &[OutdatedRow]) {
+        const HEADERS: [&str; 8] = [
+            "Package",
+            "Installed",
+            "Compatible",
+            "Latest",
+            "Bump",
+            "Yanked",
+            "MSRV",
+            "Source",
+        ];
+        let cells = rows
+            .iter()
+            .map(|row| {
+                [
+                    row.name.clone(),
+                    row.installed.clone(),
+                    row.compatible.clone().unwrap_or_else(|| "-".to_string()),
+                    row.latest.clone(),
+                    row.bump
+                        .map(|bump| bump.to_string())
+                        .unwrap_or_else(|| "-".to_string()),
+                    if row.yanked { "yes" } else { "" }.to_string(),
+                    row.msrv.clone().unwrap_or_else(|| "-".to_string()),
+                    row.source.clone(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let mut widths = HEADERS.map(|header| header.chars().count());
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        // Shorten the source column, which comes last, to fit the terminal
+        if let Ok((terminal_width, _)) = termion::terminal_size() {
+            let fixed_width = widths[..7].iter().map(|width| width + 2).sum::<usize>();
+            widths[7] = widths[7].min(
+                (terminal_width as usize)
+                    .saturating_sub(fixed_width)
+                    .max(10),
+            );
+        }
+
+        let header_line = HEADERS
+            .iter()
+            .zip(widths)
+            .map(|(header, width)| format!("{:width$}", header, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", header_line.trim_end().bold());
+
+        for (row, cells) in rows.iter().zip(cells) {
+            let [name, installed, compatible, latest, bump, yanked, msrv, source] = cells;
+            let source = truncate(&source, widths[7]);
+            let bump_text = format!("{:width$}", bump, width = widths[4]);
+            let bump_text = match row.bump {
+                Some(BumpKind::Major) => bump_text.red().to_string(),
+                Some(BumpKind::Minor) => bump_text.yellow().to_string(),
+                Some(BumpKind::Patch) => bump_text.green().to_string(),
+                None => bump_text.bright_black().to_string(),
+            };
+            let line = format!(
+                "{name:w0$}  {installed:w1$}  {compatible:w2$}  {latest:w3$}  {bump}  {yanked:w5$}  {msrv:w6$}  {source}",
+                name = name.blue(),
+                installed = installed.bright_black(),
+                compatible = compatible.green(),
+                latest = latest.green(),
+                bump = bump_text,
+                yanked = yanked.red(),
+                msrv = msrv,
+                source = source.bright_black(),
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w5 = widths[5],
+                w6 = widths[6],
+            );
+            println!("{}", line);
+        }
+    }
+}
+
+/// Shorten a text to a number of characters, marking the cut with an ellipsis.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut truncated = text
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(installed: &str, latest: &str) -> BumpKind {
+        BumpKind::between(
+            &Version::parse(installed).unwrap(),
+            &Version::parse(latest).unwrap(),
+        )
+    }
+
+    #[test]
+    fn bump_kind_between_stable_versions() {
+        assert_eq!(bump("1.2.3", "2.0.0"), BumpKind::Major);
+        assert_eq!(bump("1.2.3", "1.3.0"), BumpKind::Minor);
+        assert_eq!(bump("1.2.3", "1.2.4"), BumpKind::Patch);
+    }
+
+    #[test]
+    fn bump_kind_between_zero_versions() {
+        assert_eq!(bump("0.12.1", "0.13.0"), BumpKind::Major);
+        assert_eq!(bump("0.12.1", "0.12.2"), BumpKind::Patch);
+        assert_eq!(bump("0.0.3", "0.0.4"), BumpKind::Major);
+        assert_eq!(bump("0.9.0", "1.0.0"), BumpKind::Major);
+    }
+
+    #[test]
+    fn bump_kind_between_prereleases() {
+        assert_eq!(bump("1.0.0-alpha.1", "1.0.0"), BumpKind::Patch);
+        assert_eq!(bump("1.0.0-alpha.1", "1.1.0"), BumpKind::Minor);
+        assert_eq!(bump("1.4.0", "2.0.0-rc.1"), BumpKind::Major);
+    }
+}
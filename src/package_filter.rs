@@ -0,0 +1,128 @@
+use std::{cmp::Reverse, collections::BTreeSet, time::SystemTime};
+
+use clap::ValueEnum;
+use owo_colors::OwoColorize;
+use regex::Regex;
+
+use crate::{
+    disk_usage::binaries_size,
+    local_crates::{cargo_bin_dir, PackageSource},
+    state::Environment,
+    CratesRegistry, Package, PackageTree, PackageUpdater,
+};
+
+/// The order of listed packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    /// Alphabetically.
+    Name,
+    /// Most recently installed first.
+    Installed,
+    /// Largest binaries first.
+    Size,
+}
+
+/// The kind of source that packages were installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSource {
+    Registry,
+    Git,
+    Path,
+}
+
+impl ListSource {
+    fn matches(&self, source: &PackageSource) -> bool {
+        matches!(
+            (self, source),
+            (Self::Registry, PackageSource::Registry(_))
+                | (Self::Git, PackageSource::Git(_))
+                | (Self::Path, PackageSource::Path(_))
+        )
+    }
+}
+
+/// Which of the installed packages to list.
+#[derive(Debug, Default)]
+pub struct PackageFilter {
+    /// Matched against the package and binary names.
+    pub pattern: Option<Regex>,
+    pub source: Option<ListSource>,
+    /// Only list packages that have an update available.
+    pub outdated: bool,
+}
+
+impl PackageFilter {
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_none() && self.source.is_none() && !self.outdated
+    }
+
+    fn matches(&self, package: &Package) -> bool {
+        let matches_pattern = self.pattern.as_ref().is_none_or(|pattern| {
+            pattern.is_match(package.name())
+                || package
+                    .binaries()
+                    .iter()
+                    .any(|binary| pattern.is_match(binary.name()))
+        });
+        let matches_source = self
+            .source
+            .is_none_or(|source| source.matches(package.source()));
+        matches_pattern && matches_source
+    }
+
+    /// Remove the packages that don't match from the tree.
+    ///
+    /// The update check is the slow part, so it only runs for packages
+    /// that match the rest of the filter.
+    pub fn apply(&self, packages: &mut PackageTree) -> anyhow::Result<()> {
+        packages.retain(|package| self.matches(package));
+        if !self.outdated {
+            return Ok(());
+        }
+
+        let registry = CratesRegistry::new()?;
+        let updater = PackageUpdater::new(&registry, packages);
+        let pins = Environment::active_pins()?;
+        let mut outdated_names = BTreeSet::new();
+        for package in packages.packages() {
+            match updater.find_update(package, &pins) {
+                Ok(Some(_)) => {
+                    outdated_names.insert(package.name().to_string());
+                }
+                Ok(None) => (),
+                Err(err) => eprintln!(
+                    "{} to check package {}: {}",
+                    "Failed".yellow(),
+                    package.name().blue(),
+                    err
+                ),
+            }
+        }
+        packages.retain(|package| outdated_names.contains(package.name()));
+        Ok(())
+    }
+}
+
+/// Get the time a package was installed, from the newest of its binaries.
+fn installed_time(package: &Package, bin_dir: &std::path::Path) -> Option<SystemTime> {
+    package
+        .binaries()
+        .iter()
+        .filter_map(|binary| std::fs::symlink_metadata(bin_dir.join(binary.name())).ok())
+        .filter_map(|metadata| metadata.modified().ok())
+        .max()
+}
+
+/// Reorder the packages in the tree.
+pub fn sort_packages(packages: &mut PackageTree, sort: ListSort) -> anyhow::Result<()> {
+    let bin_dir = cargo_bin_dir()?;
+    match sort {
+        // The tree is already sorted by name
+        ListSort::Name => (),
+        ListSort::Installed => {
+            packages.sort_by_key(|package| Reverse(installed_time(package, &bin_dir)))
+        }
+        ListSort::Size => packages.sort_by_key(|package| Reverse(binaries_size(package, &bin_dir))),
+    }
+    Ok(())
+}
@@ -1,6 +1,31 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use indicatif::MultiProgress;
 use owo_colors::OwoColorize;
 
-use crate::{CratesRegistry, PackageTree};
+use crate::{
+    cargo::BuildOptions,
+    error::CapError,
+    history::{HistoryAction, HistoryEntry, HistoryOutcome},
+    license_policy::LicensePolicy,
+    local_crates::{GitReference, GitSource, PackageExecutable, PackageSource},
+    state::{CapState, Environment},
+    CratesRegistry, Package, PackageTree,
+};
+
+/// How the arguments to an uninstall are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UninstallTarget {
+    /// Arguments are package names.
+    Package,
+    /// Arguments are binary names, the owning packages are removed.
+    Binary,
+    /// Arguments are binary names, only those binaries are removed.
+    SingleBinary,
+}
 
 pub struct PackageInstaller<'a> {
     registry: &'a CratesRegistry,
@@ -18,6 +43,8 @@ impl<'a> PackageInstaller<'a> {
         locked: bool,
         forced: bool,
         nightly: bool,
+        source: Option<PackageSource>,
+        dry_run: bool,
     ) -> anyhow::Result<()> {
         let package_name = package_name.as_ref();
 
@@ -32,18 +59,64 @@ impl<'a> PackageInstaller<'a> {
             }
         }
 
-        let Ok(latest_version) = self.registry.get_latest_version(package_name) else {
-            println!("Package {} is {}.", package_name.blue(), "not available on crates.io".red());
-            return Ok(());
+        let options = BuildOptions {
+            locked,
+            toolchain: nightly.then(|| "nightly".to_string()),
+            ..Default::default()
+        };
+
+        match source {
+            Some(PackageSource::Git(git_source)) => {
+                return Self::install_git_package(
+                    package_name,
+                    &git_source,
+                    forced,
+                    &options,
+                    dry_run,
+                )
+            }
+            Some(PackageSource::Path(source_dir)) => {
+                return Self::install_path_package(
+                    package_name,
+                    &source_dir,
+                    forced,
+                    &options,
+                    dry_run,
+                )
+            }
+            Some(PackageSource::Registry(_)) | None => (),
+        }
+
+        let requirement = Environment::active_pins()?.remove(package_name);
+        let latest_version = match &requirement {
+            Some(requirement) => self
+                .registry
+                .get_latest_matching_version(package_name, requirement)?,
+            None => self.registry.get_latest_version(package_name)?,
         };
 
+        let progress = MultiProgress::new();
+        LicensePolicy::load()?.enforce(package_name, &latest_version, &progress)?;
+
+        if dry_run {
+            println!(
+                "{} {} {}.",
+                "Would install".cyan(),
+                package_name.blue(),
+                latest_version.to_string().bright_black(),
+            );
+        }
+
         match crate::cargo::install_package(
             package_name,
             latest_version.clone(),
-            locked,
+            requirement.is_some(),
             forced,
-            nightly,
+            &options,
+            &progress,
+            dry_run,
         ) {
+            Ok(_) if dry_run => (),
             Ok(_) => {
                 println!(
                     "{} {} {}.",
@@ -66,22 +139,253 @@ impl<'a> PackageInstaller<'a> {
         Ok(())
     }
 
-    pub fn uninstall_package(&self, package_name: impl AsRef<str>) -> anyhow::Result<()> {
-        let package_name = package_name.as_ref();
+    fn install_git_package(
+        package_name: &str,
+        git_source: &GitSource,
+        forced: bool,
+        options: &BuildOptions,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let progress = MultiProgress::new();
+        LicensePolicy::load()?.enforce_git(package_name, &progress)?;
+
+        if dry_run {
+            println!(
+                "{} {} from {} ({}).",
+                "Would install".cyan(),
+                package_name.blue(),
+                git_source.url.bright_black(),
+                git_source.reference.bright_black()
+            );
+        }
 
-        let Some(local_package) = self.packages.get(package_name) else {
-            println!("Package {} is {}.", package_name.blue(), "not installed".red());
-            return Ok(());
+        // The commit is only known to cargo, so it is looked up for the history
+        let commit = match &git_source.reference {
+            GitReference::Rev(rev) => Some(rev.clone()),
+            _ if dry_run => None,
+            reference => crate::git::remote_head(&git_source.url, reference)
+                .ok()
+                .flatten(),
         };
 
+        match crate::cargo::install_git_package(
+            package_name,
+            git_source,
+            commit.as_deref(),
+            forced,
+            options,
+            &progress,
+            dry_run,
+        ) {
+            Ok(_) if dry_run => (),
+            Ok(_) => {
+                println!(
+                    "{} {} from {}.",
+                    "Installed".green(),
+                    package_name.blue(),
+                    git_source.url.bright_black(),
+                );
+            }
+            Err(err) => {
+                println!(
+                    "{} to install package {} from {}.",
+                    "Failed".red(),
+                    package_name.blue(),
+                    git_source.url.bright_black()
+                );
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn install_path_package(
+        package_name: &str,
+        source_dir: &Path,
+        forced: bool,
+        options: &BuildOptions,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let progress = MultiProgress::new();
+        LicensePolicy::load()?.enforce_path(package_name, source_dir, &progress)?;
+
+        if dry_run {
+            println!(
+                "{} {} from {}.",
+                "Would install".cyan(),
+                package_name.blue(),
+                source_dir.display().bright_black()
+            );
+        }
+
+        // Hash the sources before building, so changes made during the build are noticed later
+        let source_hash = crate::path_source::source_hash(source_dir)?;
+
+        match crate::cargo::install_path_package(
+            package_name,
+            source_dir,
+            forced,
+            options,
+            &progress,
+            dry_run,
+        ) {
+            Ok(_) if dry_run => (),
+            Ok(_) => {
+                let source_hash_key = crate::path_source::source_hash_key(package_name)?;
+                CapState::modify(|state| {
+                    state.source_hashes.insert(source_hash_key, source_hash);
+                })?;
+                println!(
+                    "{} {} from {}.",
+                    "Installed".green(),
+                    package_name.blue(),
+                    source_dir.display().bright_black(),
+                );
+            }
+            Err(err) => {
+                println!(
+                    "{} to install package {} from {}.",
+                    "Failed".red(),
+                    package_name.blue(),
+                    source_dir.display().bright_black()
+                );
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn uninstall_packages(
+        &self,
+        names: &[String],
+        target: UninstallTarget,
+        assume_yes: bool,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        // Resolve arguments to packages and the binaries to be removed.
+        let mut removals: BTreeMap<&str, (&Package, BTreeSet<&str>)> = BTreeMap::new();
+        let mut missing_names = Vec::new();
+        for name in names {
+            let local_package = match target {
+                UninstallTarget::Package => self.packages.get(name),
+                UninstallTarget::Binary | UninstallTarget::SingleBinary => {
+                    self.packages.get_by_binary(name)
+                }
+            };
+
+            let Some(local_package) = local_package else {
+                let kind = match target {
+                    UninstallTarget::Package => "Package",
+                    _ => "Binary",
+                };
+                println!("{} {} is {}.", kind, name.blue(), "not installed".red());
+                missing_names.push(name.as_str());
+                continue;
+            };
+
+            let (_, binaries) = removals
+                .entry(local_package.name())
+                .or_insert_with(|| (local_package, BTreeSet::new()));
+            match target {
+                UninstallTarget::SingleBinary => {
+                    binaries.insert(name.as_str());
+                }
+                _ => binaries.extend(local_package.binaries().iter().map(PackageExecutable::name)),
+            }
+        }
+
+        // Missing packages are reported after everything else was removed.
+        let missing_result = if missing_names.is_empty() {
+            Ok(())
+        } else {
+            Err(CapError::NotInstalled(missing_names.join(", ")).into())
+        };
+
+        if removals.is_empty() {
+            return missing_result;
+        }
+
+        // List exactly what is going to disappear.
         println!(
-            "Uninstalling package {} {}.",
-            package_name.blue(),
-            local_package.version().to_string().bright_black()
+            "The following binaries {} be removed:",
+            if dry_run { "would" } else { "will" }
         );
+        for (local_package, binaries) in removals.values() {
+            println!(
+                "  {} {}: {}",
+                local_package.name().blue(),
+                local_package.version().bright_black(),
+                binaries.iter().copied().collect::<Vec<_>>().join(", ")
+            );
+        }
 
-        crate::cargo::uninstall_package(package_name)?;
+        if !assume_yes && !dry_run {
+            if !crate::helper::is_interactive() {
+                anyhow::bail!(
+                    "Refusing to remove binaries without confirmation. Pass --yes to proceed."
+                );
+            }
+            if !crate::helper::confirm("Proceed?")? {
+                println!("Aborted.");
+                return missing_result;
+            }
+        }
 
-        Ok(())
+        for (local_package, binaries) in removals.values() {
+            let is_whole_package = binaries.len() == local_package.binaries().len();
+
+            println!(
+                "{} {} {} {}.",
+                if dry_run {
+                    "Would uninstall"
+                } else {
+                    "Uninstalling"
+                },
+                if is_whole_package {
+                    "package"
+                } else {
+                    "binaries of"
+                },
+                local_package.name().blue(),
+                local_package.version().to_string().bright_black()
+            );
+
+            let binaries = if is_whole_package {
+                Vec::new()
+            } else {
+                binaries.iter().copied().collect()
+            };
+            let result = crate::cargo::uninstall_package(local_package.name(), &binaries, dry_run);
+            if dry_run {
+                result?;
+                continue;
+            }
+
+            let mut history_entry =
+                HistoryEntry::new(HistoryAction::Uninstall, local_package.name());
+            history_entry.from = Some(local_package.version().to_string());
+            history_entry.binaries = binaries.iter().map(|binary| binary.to_string()).collect();
+            let outcome = if result.is_ok() {
+                HistoryOutcome::Success
+            } else {
+                HistoryOutcome::Failed
+            };
+            if let Err(err) = history_entry.append(outcome) {
+                eprintln!("Unable to record history: {:#}", err);
+            }
+            result?;
+
+            if is_whole_package {
+                let source_hash_key = crate::path_source::source_hash_key(local_package.name())?;
+                CapState::modify(|state| {
+                    state.source_hashes.remove(&source_hash_key);
+                    state.locked_packages.remove(&source_hash_key);
+                })?;
+            }
+        }
+
+        missing_result
     }
 }
@@ -1,6 +1,17 @@
+use std::{collections::BTreeMap, fmt::Display, sync::Mutex};
+
+use anyhow::Context;
+use indicatif::MultiProgress;
 use owo_colors::OwoColorize;
 
-use crate::{CratesRegistry, Package, PackageTree};
+use crate::{
+    cargo::BuildOptions,
+    error::CapError,
+    license_policy::LicensePolicy,
+    local_crates::{cargo_bin_dir, GitSource, PackageSource},
+    state::{CapConfig, CapState, Environment, OutdatedPackage, StatusCache},
+    CratesRegistry, Package, PackageTree,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum PackageStatus {
@@ -8,23 +19,79 @@ pub enum PackageStatus {
     OutOfDate,
 }
 
+/// What an out of date package can be updated to.
+#[derive(Debug, Clone)]
+pub enum PackageUpdate {
+    /// A newer version from the registry.
+    Version(semver::Version),
+    /// A newer commit on the branch or tag that a git package tracks.
+    Commit(String),
+    /// Changed sources of a path package, at the version of its manifest.
+    SourceChanged(semver::Version),
+}
+
+impl Display for PackageUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Version(version) | Self::SourceChanged(version) => write!(f, "{}", version),
+            Self::Commit(commit) => write!(f, "{}", commit.get(..7).unwrap_or(commit)),
+        }
+    }
+}
+
+/// Compare the installed commit of a git package with the head of the branch
+/// or tag it tracks. Packages pinned to a rev are never out of date.
+fn find_git_update(git_source: &GitSource) -> anyhow::Result<Option<PackageUpdate>> {
+    let Some(remote_commit) = crate::git::remote_head(&git_source.url, &git_source.reference)?
+    else {
+        return Ok(None);
+    };
+    let is_up_to_date = git_source.commit.as_deref() == Some(remote_commit.as_str());
+    Ok((!is_up_to_date).then_some(PackageUpdate::Commit(remote_commit)))
+}
+
+/// Describe what is installed of a package, the commit for git packages.
+pub fn installed_text(package: &Package) -> String {
+    match package.source() {
+        PackageSource::Git(git_source) => git_source
+            .short_commit()
+            .map(str::to_string)
+            .unwrap_or_else(|| package.version().to_string()),
+        _ => package.version().to_string(),
+    }
+}
+
+/// Describe how an out of date package would change.
+fn update_text(package: &Package, update: &PackageUpdate) -> String {
+    let prefix = match update {
+        PackageUpdate::Version(_) => "",
+        PackageUpdate::Commit(_) => "new commits available: ",
+        PackageUpdate::SourceChanged(version) if version == package.version() => {
+            return "(sources changed since the last build)".to_string();
+        }
+        PackageUpdate::SourceChanged(_) => "sources changed: ",
+    };
+    format!(
+        "({}{} -> {})",
+        prefix,
+        installed_text(package).bright_black(),
+        update.green()
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct PackageWithStatus<'a> {
     package: &'a Package,
     status: PackageStatus,
-    latest_version: Option<semver::Version>,
+    update: Option<PackageUpdate>,
 }
 
 impl<'a> PackageWithStatus<'a> {
-    pub fn new(
-        package: &'a Package,
-        status: PackageStatus,
-        latest_version: Option<semver::Version>,
-    ) -> Self {
+    pub fn new(package: &'a Package, status: PackageStatus, update: Option<PackageUpdate>) -> Self {
         Self {
             package,
             status,
-            latest_version,
+            update,
         }
     }
 
@@ -54,46 +121,40 @@ impl<'a> PackageUpdater<'a> {
     pub fn check_package(
         &self,
         package_name: impl AsRef<str>,
-    ) -> anyhow::Result<Option<PackageWithStatus>> {
+    ) -> anyhow::Result<PackageWithStatus<'_>> {
         let Some(local_package) = self.packages.get(&package_name) else {
-            println!("Package {} is {}.", package_name.as_ref().blue(), "not installed".red());
-            return Ok(None);
+            return Err(CapError::NotInstalled(package_name.as_ref().to_string()).into());
         };
 
-        let Ok(latest_version) = self.registry.get_latest_version(local_package.name()) else {
-            println!("Package {} is {}.", local_package.name().blue(), "not available on crates.io".red());
-            return Ok(None);
-        };
-
-        if latest_version <= *local_package.version() {
+        let pins = Environment::active_pins()?;
+        let Some(update) = self.find_update(local_package, &pins)? else {
             println!(
                 "Package {} is {}.",
                 local_package.name().blue(),
                 "up to date".green()
             );
-            return Ok(Some(PackageWithStatus::new(
+            return Ok(PackageWithStatus::new(
                 local_package,
                 PackageStatus::UpToDate,
                 None,
-            )));
-        }
+            ));
+        };
 
         println!(
-            "Package {} is {} ({} -> {}).",
+            "Package {} is {} {}.",
             local_package.name().blue(),
             "out of date".yellow(),
-            local_package.version().bright_black(),
-            latest_version.to_string().green(),
+            update_text(local_package, &update),
         );
 
-        Ok(Some(PackageWithStatus::new(
+        Ok(PackageWithStatus::new(
             local_package,
             PackageStatus::OutOfDate,
-            Some(latest_version),
-        )))
+            Some(update),
+        ))
     }
 
-    pub fn check_all_packages(&self) -> anyhow::Result<Option<Vec<PackageWithStatus>>> {
+    pub fn check_all_packages(&self) -> anyhow::Result<Option<Vec<PackageWithStatus<'_>>>> {
         // Gather package status for each installed package.
         let statuses = self.get_package_statuses();
         let outdated_packages = statuses
@@ -111,11 +172,7 @@ impl<'a> PackageUpdater<'a> {
         // Print out-of-date packages.
         for package in &outdated_packages {
             let status_text = "out of date".yellow();
-            let version_text = format!(
-                "({} -> {})",
-                package.package().version().bright_black(),
-                package.latest_version.clone().unwrap().green()
-            );
+            let version_text = update_text(package.package(), package.update.as_ref().unwrap());
             println!(
                 "{package_name:padding$} is {status_text} {version_text}",
                 package_name = package.package().name().blue(),
@@ -128,30 +185,57 @@ impl<'a> PackageUpdater<'a> {
         ))
     }
 
-    pub fn update_package(&self, package_name: impl AsRef<str>) -> anyhow::Result<()> {
-        let Ok(Some(package)) = self.check_package(package_name) else {
-            return Ok(());
-        };
+    /// Write the result of a full check to the status cache.
+    pub fn save_status(outdated_packages: &[PackageWithStatus]) -> anyhow::Result<()> {
+        let outdated = outdated_packages
+            .iter()
+            .filter_map(|package_with_status| {
+                let package = package_with_status.package();
+                Some(OutdatedPackage {
+                    name: package.name().to_string(),
+                    installed: installed_text(package),
+                    available: package_with_status.update.as_ref()?.to_string(),
+                })
+            })
+            .collect();
+        StatusCache::new(outdated).save()
+    }
+
+    /// Update a single package.
+    ///
+    /// With `locked` set, the package is built with or without its lockfile.
+    /// Otherwise it is built the way it was built before.
+    pub fn update_package(
+        &self,
+        package_name: impl AsRef<str>,
+        locked: Option<bool>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let package = self.check_package(package_name)?;
 
         if package.is_up_to_date() {
             return Ok(());
         }
 
         let local_package = package.package();
-        let latest_version = package.latest_version.unwrap();
+        let update = package.update.unwrap();
 
-        match crate::cargo::update_package(
-            local_package.name(),
-            local_package.version().clone(),
-            latest_version.clone(),
+        match Self::run_update(
+            local_package,
+            &update,
+            locked,
+            &MultiProgress::new(),
+            dry_run,
         ) {
+            Ok(_) if dry_run => (),
             Ok(_) => {
+                StatusCache::remove_package(local_package.name())?;
                 println!(
                     "{} {} from {} to {}.",
                     "Updated".green(),
                     local_package.name().blue(),
-                    local_package.version().bright_black(),
-                    latest_version.green(),
+                    installed_text(local_package).bright_black(),
+                    update.green(),
                 );
             }
             Err(err) => {
@@ -160,10 +244,19 @@ impl<'a> PackageUpdater<'a> {
                     "Failed".red(),
                     local_package.name().blue(),
                 );
-                println!(
-                    "You may need to run {}.",
-                    format!("cap update --locked {}", local_package.name()).bright_black()
+                let is_build_failure = matches!(
+                    err.downcast_ref::<CapError>(),
+                    Some(CapError::BuildFailed(_))
                 );
+                let was_tried_locked = locked
+                    .unwrap_or_else(|| BuildOptions::recorded(local_package.name()).locked)
+                    || CapConfig::load()?.update.retry_toggling_locked;
+                if is_build_failure && !was_tried_locked {
+                    println!(
+                        "You may need to run {}.",
+                        format!("cap update --locked {}", local_package.name()).bright_black()
+                    );
+                }
                 return Err(err);
             }
         }
@@ -171,7 +264,16 @@ impl<'a> PackageUpdater<'a> {
         Ok(())
     }
 
-    pub fn update_all_packages(&self) -> anyhow::Result<()> {
+    /// Update all outdated packages, running up to `jobs` builds at a time.
+    ///
+    /// Each running build gets its own progress bar. After the first failure,
+    /// running builds are finished but no new ones are started.
+    pub fn update_all_packages(
+        &self,
+        jobs: usize,
+        locked: Option<bool>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
         // Gather package status for each installed package.
         let Ok(Some(outdated_packages)) = self.check_all_packages() else {
             return Ok(());
@@ -179,61 +281,272 @@ impl<'a> PackageUpdater<'a> {
 
         println!(
             "{} {} {}...",
-            "Updating".green(),
+            if dry_run {
+                "Would update".cyan().to_string()
+            } else {
+                "Updating".green().to_string()
+            },
             outdated_packages.len(),
             crate::helper::pluralize("package", "packages", outdated_packages.len())
         );
 
-        for package_with_status in &outdated_packages {
-            let package_name = package_with_status.package().name();
-            let local_version = package_with_status.package().version();
-            let latest_version = package_with_status.latest_version.clone().unwrap();
-
-            match crate::cargo::update_package(
-                package_name,
-                local_version.clone(),
-                latest_version.clone(),
-            ) {
-                Ok(_) => {
-                    println!(
-                        "{} {} from {} to {}.",
-                        "Updated".green(),
-                        package_name.blue(),
-                        local_version.bright_black(),
-                        latest_version.green(),
-                    );
+        let updates = outdated_packages
+            .into_iter()
+            .map(|package_with_status| {
+                (
+                    package_with_status.package(),
+                    package_with_status.update.unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Planned commands are printed in order
+        let jobs = if dry_run { 1 } else { jobs.max(1) };
+        let progress = MultiProgress::new();
+        let queue = Mutex::new(updates.into_iter());
+        let first_error = Mutex::new(None);
+
+        std::thread::scope(|scope| {
+            for _ in 0..jobs {
+                scope.spawn(|| loop {
+                    if first_error.lock().unwrap().is_some() {
+                        break;
+                    }
+                    let Some((package, update)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+
+                    let mut result = Self::run_update(package, &update, locked, &progress, dry_run);
+                    if result.is_ok() && !dry_run {
+                        result = StatusCache::remove_package(package.name());
+                    }
+                    progress.suspend(|| match &result {
+                        Ok(_) if dry_run => (),
+                        Ok(_) => println!(
+                            "{} {} from {} to {}.",
+                            "Updated".green(),
+                            package.name().blue(),
+                            installed_text(package).bright_black(),
+                            update.green(),
+                        ),
+                        Err(_) => println!(
+                            "{} to update package {}.",
+                            "Failed".red(),
+                            package.name().blue(),
+                        ),
+                    });
+                    if let Err(err) = result {
+                        first_error.lock().unwrap().get_or_insert(err);
+                    }
+                });
+            }
+        });
+
+        match first_error.into_inner().unwrap() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the latest version of a package, staying within its pin if there is one.
+    fn latest_version(
+        &self,
+        package_name: &str,
+        pins: &BTreeMap<String, semver::VersionReq>,
+    ) -> anyhow::Result<semver::Version> {
+        match pins.get(package_name) {
+            Some(requirement) => self
+                .registry
+                .get_latest_matching_version(package_name, requirement),
+            None => self.registry.get_latest_version(package_name),
+        }
+    }
+
+    /// Find out what a package can be updated to, if anything.
+    ///
+    /// Registry packages are compared with the index, git packages with the
+    /// head of the branch or tag they track. Packages pinned to a git rev
+    /// are never out of date. Path packages are out of date when their
+    /// manifest version was bumped or their sources changed since cap last
+    /// built them, or were modified after their binaries if cap never did.
+    pub fn find_update(
+        &self,
+        package: &Package,
+        pins: &BTreeMap<String, semver::VersionReq>,
+    ) -> anyhow::Result<Option<PackageUpdate>> {
+        match package.source() {
+            source @ PackageSource::Registry(_) if source.is_crates_io() => {
+                let latest_version = self.latest_version(package.name(), pins)?;
+                Ok((latest_version > *package.version())
+                    .then_some(PackageUpdate::Version(latest_version)))
+            }
+            // Only the crates.io index is available to compare with
+            PackageSource::Registry(url) => Err(CapError::UnsupportedSource(url.clone()))
+                .context("Checking packages from other registries than crates.io is not supported"),
+            PackageSource::Git(git_source) => find_git_update(git_source),
+            PackageSource::Path(source_dir) => {
+                if !source_dir.is_dir() {
+                    anyhow::bail!("Source dir {} does not exist.", source_dir.display());
                 }
-                Err(err) => {
-                    println!(
-                        "{} to update package {}.",
-                        "Failed".red(),
-                        package_name.blue(),
-                    );
-                    return Err(err);
+                let manifest_version = crate::path_source::manifest_version(source_dir)?
+                    .unwrap_or_else(|| package.version().clone());
+                if manifest_version > *package.version() {
+                    return Ok(Some(PackageUpdate::SourceChanged(manifest_version)));
                 }
+
+                let source_hash_key = crate::path_source::source_hash_key(package.name())?;
+                let is_changed = match CapState::load()?.source_hashes.get(&source_hash_key) {
+                    Some(source_hash) => {
+                        crate::path_source::source_hash(source_dir)? != *source_hash
+                    }
+                    None => {
+                        let bin_dir = cargo_bin_dir()?;
+                        let built_at = package
+                            .binaries()
+                            .iter()
+                            .filter_map(|binary| {
+                                let metadata = bin_dir.join(binary.name()).metadata().ok()?;
+                                metadata.modified().ok()
+                            })
+                            .min();
+                        let modified_at = crate::path_source::last_modified(source_dir)?;
+                        match (built_at, modified_at) {
+                            (Some(built_at), Some(modified_at)) => modified_at > built_at,
+                            _ => false,
+                        }
+                    }
+                };
+                Ok(is_changed.then_some(PackageUpdate::SourceChanged(manifest_version)))
             }
         }
+    }
+
+    /// Build a package at its update.
+    ///
+    /// If `update.retry_toggling_locked` is set in the cap config, a failed
+    /// build is retried with the opposite `--locked` setting. The setting
+    /// that worked is remembered for the next update.
+    fn run_update(
+        package: &Package,
+        update: &PackageUpdate,
+        locked: Option<bool>,
+        progress: &MultiProgress,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let mut options = BuildOptions::recorded(package.name());
+        if let Some(locked) = locked {
+            options.locked = locked;
+        }
 
-        Ok(())
+        let result = Self::build_update(package, update, &options, progress, dry_run);
+        let is_build_failure = result.as_ref().is_err_and(|err| {
+            matches!(
+                err.downcast_ref::<CapError>(),
+                Some(CapError::BuildFailed(_))
+            )
+        });
+        if !is_build_failure || !CapConfig::load()?.update.retry_toggling_locked {
+            return result;
+        }
+
+        options.locked = !options.locked;
+        progress.suspend(|| {
+            println!(
+                "{} {} {}...",
+                "Retrying".yellow(),
+                package.name().blue(),
+                if options.locked {
+                    "with --locked"
+                } else {
+                    "without --locked"
+                }
+            )
+        });
+        Self::build_update(package, update, &options, progress, dry_run)
     }
 
-    fn get_package_statuses(&self) -> Vec<PackageWithStatus> {
+    fn build_update(
+        package: &Package,
+        update: &PackageUpdate,
+        options: &BuildOptions,
+        progress: &MultiProgress,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        match (update, package.source()) {
+            (PackageUpdate::Version(version), _) => {
+                LicensePolicy::load()?.enforce(package.name(), version, progress)?;
+                crate::cargo::update_package(
+                    package.name(),
+                    package.version().clone(),
+                    version.clone(),
+                    options,
+                    progress,
+                    dry_run,
+                )
+            }
+            (PackageUpdate::Commit(commit), PackageSource::Git(git_source)) => {
+                LicensePolicy::load()?.enforce_git(package.name(), progress)?;
+                crate::cargo::install_git_package(
+                    package.name(),
+                    git_source,
+                    Some(commit),
+                    true,
+                    options,
+                    progress,
+                    dry_run,
+                )
+            }
+            (PackageUpdate::SourceChanged(_), PackageSource::Path(source_dir)) => {
+                LicensePolicy::load()?.enforce_path(package.name(), source_dir, progress)?;
+                // Hash the sources before building, so changes made during the build are noticed later
+                let source_hash = crate::path_source::source_hash(source_dir)?;
+                crate::cargo::install_path_package(
+                    package.name(),
+                    source_dir,
+                    true,
+                    options,
+                    progress,
+                    dry_run,
+                )?;
+                if dry_run {
+                    return Ok(());
+                }
+                let source_hash_key = crate::path_source::source_hash_key(package.name())?;
+                CapState::modify(|state| {
+                    state.source_hashes.insert(source_hash_key, source_hash);
+                })
+            }
+            (PackageUpdate::Commit(_), _) => {
+                anyhow::bail!("Package {} is not installed from git.", package.name())
+            }
+            (PackageUpdate::SourceChanged(_), _) => {
+                anyhow::bail!("Package {} is not installed from a path.", package.name())
+            }
+        }
+    }
+
+    fn get_package_statuses(&self) -> Vec<PackageWithStatus<'_>> {
+        let pins = Environment::active_pins().unwrap_or_default();
         let mut packages_with_status = Vec::new();
         for package in self.packages.packages() {
-            let Ok(latest_version) = self.registry.get_latest_version(package.name()) else {
-                continue;
+            let update = match self.find_update(package, &pins) {
+                Ok(update) => update,
+                Err(err) => {
+                    println!(
+                        "{} to check package {}: {}",
+                        "Failed".yellow(),
+                        package.name().blue(),
+                        err
+                    );
+                    continue;
+                }
             };
-            let is_up_to_date = latest_version <= *package.version();
-            let status = if is_up_to_date {
+            let status = if update.is_none() {
                 PackageStatus::UpToDate
             } else {
                 PackageStatus::OutOfDate
             };
-            packages_with_status.push(PackageWithStatus::new(
-                package,
-                status,
-                Some(latest_version),
-            ));
+            packages_with_status.push(PackageWithStatus::new(package, status, update));
         }
         packages_with_status
     }
@@ -246,3 +559,107 @@ impl<'a> PackageUpdater<'a> {
             .unwrap_or(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use super::*;
+    use crate::local_crates::GitReference;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=cap", "-c", "user.email=cap@localhost"])
+            .args(["-c", "init.defaultBranch=main"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// Create a bare repository with one commit on `main`, tagged `v1`.
+    fn bare_repository(dir: &Path) -> (String, String) {
+        let remote_dir = dir.join("remote.git");
+        let work_dir = dir.join("work");
+        std::fs::create_dir_all(&remote_dir).unwrap();
+        std::fs::create_dir_all(&work_dir).unwrap();
+        git(&remote_dir, &["init", "--bare", "--quiet"]);
+        git(&work_dir, &["init", "--quiet"]);
+        git(
+            &work_dir,
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+        git(&work_dir, &["tag", "-a", "v1", "-m", "v1"]);
+        let remote_url = remote_dir.display().to_string();
+        git(&work_dir, &["push", "--quiet", &remote_url, "main", "v1"]);
+        let commit = git(&work_dir, &["rev-parse", "HEAD"]);
+        (remote_url, commit)
+    }
+
+    fn git_source(url: &str, reference: GitReference, commit: &str) -> GitSource {
+        GitSource {
+            url: url.to_string(),
+            reference,
+            commit: Some(commit.to_string()),
+        }
+    }
+
+    #[test]
+    fn git_update_compares_with_the_remote_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let (remote_url, first_commit) = bare_repository(dir.path());
+        let branch = GitReference::Branch("main".to_string());
+
+        let up_to_date = git_source(&remote_url, branch.clone(), &first_commit);
+        assert!(find_git_update(&up_to_date).unwrap().is_none());
+
+        let work_dir = dir.path().join("work");
+        git(
+            &work_dir,
+            &["commit", "--quiet", "--allow-empty", "-m", "second"],
+        );
+        git(&work_dir, &["push", "--quiet", &remote_url, "main"]);
+        let second_commit = git(&work_dir, &["rev-parse", "HEAD"]);
+
+        match find_git_update(&up_to_date).unwrap() {
+            Some(PackageUpdate::Commit(commit)) => assert_eq!(commit, second_commit),
+            update => panic!("Unexpected update {:?}", update),
+        }
+        let default_branch = git_source(&remote_url, GitReference::DefaultBranch, &first_commit);
+        assert!(find_git_update(&default_branch).unwrap().is_some());
+    }
+
+    #[test]
+    fn git_update_peels_annotated_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let (remote_url, commit) = bare_repository(dir.path());
+        let tag = git_source(&remote_url, GitReference::Tag("v1".to_string()), &commit);
+        assert!(find_git_update(&tag).unwrap().is_none());
+    }
+
+    #[test]
+    fn git_update_ignores_pinned_revs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (remote_url, _) = bare_repository(dir.path());
+        let rev = git_source(
+            &remote_url,
+            GitReference::Rev("0123456".to_string()),
+            "0123456",
+        );
+        assert!(find_git_update(&rev).unwrap().is_none());
+    }
+
+    #[test]
+    fn git_update_fails_for_missing_branches() {
+        let dir = tempfile::tempdir().unwrap();
+        let (remote_url, commit) = bare_repository(dir.path());
+        let branch = git_source(
+            &remote_url,
+            GitReference::Branch("gone".to_string()),
+            &commit,
+        );
+        assert!(find_git_update(&branch).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+
+use crate::local_crates::install_root;
+
+/// Dirs that don't hold sources and are skipped when looking for changes.
+const IGNORED_DIRS: &[&str] = &["target", ".git"];
+
+/// Read the `[package]` table of the manifest in a source dir.
+fn read_package_table(dir: &Path) -> anyhow::Result<toml::Table> {
+    let manifest_path = dir.join("Cargo.toml");
+    let manifest_str = read_to_string(&manifest_path)
+        .with_context(|| format!("Unable to read {}.", manifest_path.display()))?;
+    let manifest: toml::Table = toml::from_str(&manifest_str)
+        .with_context(|| format!("Unable to parse {}.", manifest_path.display()))?;
+    match manifest
+        .get("package")
+        .and_then(|package| package.as_table())
+    {
+        Some(package) => Ok(package.clone()),
+        None => anyhow::bail!("{} has no [package] table.", manifest_path.display()),
+    }
+}
+
+/// Get the name of the package in a source dir.
+pub fn manifest_name(dir: &Path) -> anyhow::Result<String> {
+    read_package_table(dir)?
+        .get("name")
+        .and_then(|name| name.as_str())
+        .map(str::to_string)
+        .context("Package manifest has no name.")
+}
+
+/// Get the version of the package in a source dir.
+///
+/// Returns `None` if the version isn't a plain string, like with
+/// `version.workspace = true`.
+pub fn manifest_version(dir: &Path) -> anyhow::Result<Option<semver::Version>> {
+    Ok(read_package_table(dir)?
+        .get("version")
+        .and_then(|version| version.as_str())
+        .and_then(|version| semver::Version::parse(version).ok()))
+}
+
+/// List all source files in a dir, sorted by path.
+fn source_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending_dirs = vec![dir.to_path_buf()];
+    while let Some(current_dir) = pending_dirs.pop() {
+        for entry in std::fs::read_dir(&current_dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                let is_ignored = IGNORED_DIRS
+                    .iter()
+                    .any(|ignored| entry.file_name() == *ignored);
+                if !is_ignored {
+                    pending_dirs.push(entry.path());
+                }
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Hash the names and contents of all source files in a dir.
+///
+/// This uses 64-bit FNV-1a, which is stable across Rust versions,
+/// so hashes stored in the cap state stay comparable.
+pub fn source_hash(dir: &Path) -> anyhow::Result<String> {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    for path in source_files(dir)? {
+        let relative_path = path.strip_prefix(dir).unwrap_or(&path);
+        feed(relative_path.to_string_lossy().as_bytes());
+        feed(&[0]);
+        feed(&std::fs::read(&path)?);
+        feed(&[0]);
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+/// Get the key that the source hash of a path package is stored under.
+///
+/// The same package can be installed into several roots, like environments,
+/// from different sources, so the key includes the install root.
+pub fn source_hash_key(package_name: &str) -> anyhow::Result<String> {
+    Ok(format!("{} ({})", package_name, install_root()?.display()))
+}
+
+/// Get the time any source file in a dir was last modified.
+pub fn last_modified(dir: &Path) -> anyhow::Result<Option<SystemTime>> {
+    Ok(source_files(dir)?
+        .iter()
+        .filter_map(|path| path.metadata().and_then(|meta| meta.modified()).ok())
+        .max())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    fn source_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        create_dir_all(dir.path().join("src")).unwrap();
+        write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"foo\"\nversion = \"1.2.3\"\n",
+        )
+        .unwrap();
+        write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn source_hash_is_stable() {
+        let dir = source_dir();
+        let hash = source_hash(dir.path()).unwrap();
+        assert_eq!(hash.len(), 16);
+        assert_eq!(source_hash(dir.path()).unwrap(), hash);
+
+        // The hash doesn't depend on where the sources are
+        let other_dir = source_dir();
+        assert_eq!(source_hash(other_dir.path()).unwrap(), hash);
+    }
+
+    #[test]
+    fn source_hash_changes_with_sources() {
+        let dir = source_dir();
+        let hash = source_hash(dir.path()).unwrap();
+
+        write(dir.path().join("src/main.rs"), "fn main() { }\n").unwrap();
+        let edited_hash = source_hash(dir.path()).unwrap();
+        assert_ne!(edited_hash, hash);
+
+        std::fs::rename(
+            dir.path().join("src/main.rs"),
+            dir.path().join("src/lib.rs"),
+        )
+        .unwrap();
+        assert_ne!(source_hash(dir.path()).unwrap(), edited_hash);
+    }
+
+    #[test]
+    fn source_hash_ignores_build_output() {
+        let dir = source_dir();
+        let hash = source_hash(dir.path()).unwrap();
+
+        create_dir_all(dir.path().join("target/debug")).unwrap();
+        write(dir.path().join("target/debug/foo"), "binary").unwrap();
+        create_dir_all(dir.path().join(".git")).unwrap();
+        write(dir.path().join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        assert_eq!(source_hash(dir.path()).unwrap(), hash);
+    }
+
+    #[test]
+    fn manifest_fields() {
+        let dir = source_dir();
+        assert_eq!(manifest_name(dir.path()).unwrap(), "foo");
+        assert_eq!(
+            manifest_version(dir.path()).unwrap(),
+            Some(semver::Version::new(1, 2, 3))
+        );
+    }
+}
@@ -0,0 +1,24 @@
+mod cap_config;
+mod cap_state;
+mod environment;
+mod status_cache;
+
+pub use cap_config::{CapConfig, LicensesConfig, UnlistedLicenses};
+pub use cap_state::CapState;
+pub use environment::{Environment, DEFAULT_ENV_NAME};
+pub use status_cache::{OutdatedPackage, StatusCache};
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+/// Get the directory cap keeps its own state in.
+///
+/// This is `$CAP_HOME` if set, and `~/.cap` otherwise.
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    if let Some(cap_home) = std::env::var_os("CAP_HOME") {
+        return Ok(PathBuf::from(cap_home));
+    }
+    let home_dir = home::home_dir().context("Unable to find home dir.")?;
+    Ok(home_dir.join(".cap"))
+}
@@ -0,0 +1,96 @@
+use std::{fs::read_to_string, path::PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// Settings of `cap check`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CheckConfig {
+    /// How often `cap check --background` refreshes the status cache.
+    pub interval_minutes: u64,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_minutes: 24 * 60,
+        }
+    }
+}
+
+/// Settings of `cap update`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Retry failed builds with the opposite `--locked` setting.
+    pub retry_toggling_locked: bool,
+}
+
+/// Settings of `cap audit`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Path of a local checkout of the RustSec advisory database.
+    pub advisory_db: Option<PathBuf>,
+}
+
+/// Settings of the crates.io API client.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Base URL of the API, `https://crates.io` by default.
+    pub url: Option<String>,
+}
+
+/// What happens to licenses that are neither allowed nor denied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnlistedLicenses {
+    #[default]
+    Warn,
+    Deny,
+}
+
+/// The license policy enforced by `cap install` and `cap update`.
+///
+/// License names are SPDX identifiers, like `MIT` or `Apache-2.0`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LicensesConfig {
+    /// Licenses that may be installed. If empty, all licenses that aren't denied are allowed.
+    pub allow: Vec<String>,
+    /// Licenses that may never be installed.
+    pub deny: Vec<String>,
+    pub unlisted: UnlistedLicenses,
+}
+
+/// User configuration of cap.
+///
+/// This is read from `config.toml` in the cap state dir. Missing files
+/// and missing keys fall back to the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CapConfig {
+    pub check: CheckConfig,
+    pub update: UpdateConfig,
+    pub audit: AuditConfig,
+    pub api: ApiConfig,
+    pub licenses: LicensesConfig,
+}
+
+impl CapConfig {
+    fn path() -> anyhow::Result<PathBuf> {
+        Ok(super::state_dir()?.join("config.toml"))
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let config_str = read_to_string(&path).context("Unable to read cap config.")?;
+        toml::from_str(&config_str)
+            .with_context(|| format!("Unable to parse cap config {}.", path.display()))
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::read_to_string,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Persistent state cap keeps between runs.
+///
+/// This is stored as `state.toml` in the cap state dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CapState {
+    /// Binaries in the cargo bin dir that cleanup should leave alone.
+    #[serde(default)]
+    pub ignored_binaries: BTreeSet<String>,
+    /// Name of the environment packages are managed in, if not the default one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_env: Option<String>,
+    /// Hashes of the sources that path packages were last built from,
+    /// keyed by package name and install root.
+    #[serde(default)]
+    pub source_hashes: BTreeMap<String, String>,
+    /// Packages that were last built with `--locked`, which updates keep using,
+    /// keyed by package name and install root like the source hashes.
+    #[serde(default)]
+    pub locked_packages: BTreeSet<String>,
+}
+
+impl CapState {
+    fn path() -> anyhow::Result<PathBuf> {
+        Ok(super::state_dir()?.join("state.toml"))
+    }
+
+    /// Load the state, or return an empty state if none was saved yet.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let state_str = read_to_string(&path).context("Unable to read cap state.")?;
+        toml::from_str(&state_str).context("Unable to parse cap state.")
+    }
+
+    /// Load the state, change it and save it again.
+    ///
+    /// Concurrent modifications from within cap are serialized, so that
+    /// parallel builds don't overwrite each other's changes.
+    pub fn modify(f: impl FnOnce(&mut Self)) -> anyhow::Result<()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut state = Self::load()?;
+        f(&mut state);
+        state.save()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("Unable to create cap state dir.")?;
+        }
+        let state_str = toml::to_string_pretty(self)?;
+        std::fs::write(path, state_str).context("Unable to write cap state.")
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::CapState;
+
+/// Name that refers to the regular install root instead of an environment.
+pub const DEFAULT_ENV_NAME: &str = "default";
+
+/// Settings of an environment, stored as `env.toml` in its dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EnvManifest {
+    /// Toolchain to build packages with, like `stable` or `nightly-2024-01-01`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// Version requirements that installs and updates stay within.
+    #[serde(default)]
+    pub pins: BTreeMap<String, semver::VersionReq>,
+}
+
+/// A named environment with its own install root.
+///
+/// Environments live in `envs/<name>` in the cap state dir. Cargo installs
+/// into that dir like into any other root, so each environment gets its own
+/// `bin` dir and its own `.crates.toml`.
+#[derive(Debug)]
+pub struct Environment {
+    name: String,
+    dir: PathBuf,
+    manifest: EnvManifest,
+}
+
+impl Environment {
+    fn envs_dir() -> anyhow::Result<PathBuf> {
+        Ok(super::state_dir()?.join("envs"))
+    }
+
+    fn manifest_path(dir: &Path) -> PathBuf {
+        dir.join("env.toml")
+    }
+
+    /// Check that a name can't escape the envs dir, like `../x` could.
+    fn validate_name(name: &str) -> anyhow::Result<()> {
+        let is_valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid_name {
+            anyhow::bail!(
+                "Invalid environment name {}. Use letters, digits, - and _ only.",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    /// Create a new, empty environment.
+    pub fn create(name: &str, toolchain: Option<String>) -> anyhow::Result<Self> {
+        Self::validate_name(name)?;
+        if name == DEFAULT_ENV_NAME {
+            anyhow::bail!("The environment name {} is reserved.", name);
+        }
+
+        let dir = Self::envs_dir()?.join(name);
+        if dir.exists() {
+            anyhow::bail!("Environment {} already exists.", name);
+        }
+        std::fs::create_dir_all(dir.join("bin")).context("Unable to create environment dir.")?;
+
+        let env = Self {
+            name: name.to_string(),
+            dir,
+            manifest: EnvManifest {
+                toolchain,
+                ..Default::default()
+            },
+        };
+        env.save()?;
+        Ok(env)
+    }
+
+    /// Load an existing environment.
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        Self::validate_name(name)?;
+        let dir = Self::envs_dir()?.join(name);
+        let manifest_path = Self::manifest_path(&dir);
+        if !manifest_path.is_file() {
+            anyhow::bail!("Environment {} does not exist.", name);
+        }
+        let manifest_str = read_to_string(&manifest_path)
+            .with_context(|| format!("Unable to read manifest of environment {}.", name))?;
+        let manifest = toml::from_str(&manifest_str)
+            .with_context(|| format!("Unable to parse manifest of environment {}.", name))?;
+        Ok(Self {
+            name: name.to_string(),
+            dir,
+            manifest,
+        })
+    }
+
+    /// List all environments, sorted by name.
+    pub fn list() -> anyhow::Result<Vec<Self>> {
+        let envs_dir = Self::envs_dir()?;
+        if !envs_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names = std::fs::read_dir(envs_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| Self::manifest_path(&entry.path()).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| Self::validate_name(name).is_ok())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.iter().map(|name| Self::load(name)).collect()
+    }
+
+    /// Get the active environment, if any.
+    pub fn active() -> anyhow::Result<Option<Self>> {
+        match CapState::load()?.active_env {
+            Some(name) => Self::load(&name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the version pins of the active environment, if any.
+    pub fn active_pins() -> anyhow::Result<BTreeMap<String, semver::VersionReq>> {
+        Ok(Self::active()?
+            .map(|env| env.manifest.pins)
+            .unwrap_or_default())
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let manifest_str = toml::to_string_pretty(&self.manifest)?;
+        std::fs::write(Self::manifest_path(&self.dir), manifest_str)
+            .context("Unable to write environment manifest.")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The install root of this environment.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn bin_dir(&self) -> PathBuf {
+        self.dir.join("bin")
+    }
+
+    pub fn manifest(&self) -> &EnvManifest {
+        &self.manifest
+    }
+
+    pub fn manifest_mut(&mut self) -> &mut EnvManifest {
+        &mut self.manifest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_name_rejects_paths() {
+        for name in ["../../x", "a/b", "..", ".", "", "a\\b"] {
+            assert!(Environment::validate_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn validate_name_accepts_plain_names() {
+        for name in ["ci", "dev-2024", "local_tools"] {
+            assert!(Environment::validate_name(name).is_ok(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs::{read_to_string, OpenOptions},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// How long a background refresh may take before another one may start.
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A package that `cap check` found to be out of date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub installed: String,
+    pub available: String,
+}
+
+/// The result of the last full `cap check`.
+///
+/// This is stored per install root in the `status` dir of the cap state dir,
+/// so the update status can be shown without opening the index.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StatusCache {
+    /// Unix timestamp of the check.
+    pub checked_at: u64,
+    #[serde(default)]
+    pub outdated: Vec<OutdatedPackage>,
+}
+
+impl StatusCache {
+    fn path() -> anyhow::Result<PathBuf> {
+        Ok(Self::base_path()?.with_extension("toml"))
+    }
+
+    fn refresh_marker_path() -> anyhow::Result<PathBuf> {
+        Ok(Self::base_path()?.with_extension("refreshing"))
+    }
+
+    /// The status depends on the install root, so each root gets its own files.
+    fn base_path() -> anyhow::Result<PathBuf> {
+        let root = crate::local_crates::install_root()?;
+        Ok(super::state_dir()?
+            .join("status")
+            .join(root_file_name(&root.to_string_lossy())))
+    }
+
+    pub fn new(outdated: Vec<OutdatedPackage>) -> Self {
+        let checked_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            checked_at,
+            outdated,
+        }
+    }
+
+    /// Load the cached status, if there is one.
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let cache_str = read_to_string(&path).context("Unable to read status cache.")?;
+        toml::from_str(&cache_str)
+            .map(Some)
+            .context("Unable to parse status cache.")
+    }
+
+    /// Save the status, which also ends a background refresh.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("Unable to create status cache dir.")?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .context("Unable to write status cache.")?;
+        let _ = std::fs::remove_file(Self::refresh_marker_path()?);
+        Ok(())
+    }
+
+    /// Drop a package from the cached status after it was updated.
+    pub fn remove_package(package_name: &str) -> anyhow::Result<()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(mut cache) = Self::load()? else {
+            return Ok(());
+        };
+        if !cache
+            .outdated
+            .iter()
+            .any(|package| package.name == package_name)
+        {
+            return Ok(());
+        }
+        cache
+            .outdated
+            .retain(|package| package.name != package_name);
+        std::fs::write(Self::path()?, toml::to_string_pretty(&cache)?)
+            .context("Unable to write status cache.")
+    }
+
+    /// Time since the check.
+    pub fn age(&self) -> Duration {
+        let checked_at = UNIX_EPOCH + Duration::from_secs(self.checked_at);
+        SystemTime::now()
+            .duration_since(checked_at)
+            .unwrap_or_default()
+    }
+
+    /// Claim the right to refresh the status in the background.
+    ///
+    /// Returns `false` if another refresh is already running.
+    pub fn start_refresh() -> anyhow::Result<bool> {
+        let marker_path = Self::refresh_marker_path()?;
+        if let Some(parent) = marker_path.parent() {
+            std::fs::create_dir_all(parent).context("Unable to create status cache dir.")?;
+        }
+
+        // A refresh that crashed leaves its marker behind, which expires eventually.
+        let is_stale = marker_path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|elapsed| elapsed > REFRESH_TIMEOUT);
+        if is_stale {
+            let _ = std::fs::remove_file(&marker_path);
+        }
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&marker_path)
+        {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(err) => Err(err).context("Unable to start status refresh."),
+        }
+    }
+}
+
+/// Turn an install root into a file name without path separators.
+fn root_file_name(root: &str) -> String {
+    root.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_roots_map_to_distinct_file_names() {
+        assert_eq!(root_file_name("/home/me/.cargo"), "_home_me__cargo");
+        assert_ne!(
+            root_file_name("/home/me/.cargo"),
+            root_file_name("/opt/tools")
+        );
+        assert!(!root_file_name(r"C:\Users\me\.cargo").contains(['\\', ':']));
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+
+use crate::{
+    package_updater::PackageUpdater,
+    state::{CapConfig, StatusCache},
+    CratesRegistry, PackageTree,
+};
+
+/// Check all packages for updates and write the result to the status cache.
+///
+/// Returns `true` if updates are available.
+pub fn check_all_packages(update_index: bool) -> anyhow::Result<bool> {
+    let mut registry = CratesRegistry::new()?;
+    if update_index {
+        registry.update_index_with_progress()?;
+    }
+    let packages = PackageTree::build()?;
+    let updater = PackageUpdater::new(&registry, &packages);
+    let outdated_packages = updater.check_all_packages()?;
+    PackageUpdater::save_status(outdated_packages.as_deref().unwrap_or_default())?;
+    Ok(outdated_packages.is_some())
+}
+
+/// Whether the cached status is older than the configured check interval.
+pub fn is_stale(cache: &StatusCache) -> anyhow::Result<bool> {
+    let interval = Duration::from_secs(CapConfig::load()?.check.interval_minutes * 60);
+    Ok(cache.age() >= interval)
+}
+
+/// Print the cached update status.
+///
+/// The short form is a single line meant for shell prompts,
+/// which is empty when everything is up to date.
+pub fn print_status(cache: &StatusCache, short: bool) {
+    let count = cache.outdated.len();
+    let summary = format!(
+        "{} tool {} available",
+        count,
+        crate::helper::pluralize("update", "updates", count)
+    );
+
+    if short {
+        if count > 0 {
+            println!("{}", summary);
+        }
+        return;
+    }
+
+    let age_text = format!("(checked {})", crate::helper::format_age(cache.age()));
+    if count == 0 {
+        println!(
+            "All packages are {} {}",
+            "up to date".green(),
+            age_text.bright_black()
+        );
+        return;
+    }
+    println!("{} {}", summary.yellow(), age_text.bright_black());
+    for package in &cache.outdated {
+        println!(
+            "  {} ({} -> {})",
+            package.name.blue(),
+            package.installed.bright_black(),
+            package.available.green()
+        );
+    }
+}
+
+/// Refresh the status cache in a detached process, unless it is recent enough.
+pub fn refresh_in_background() -> anyhow::Result<()> {
+    // An unreadable cache is refreshed like a stale one
+    if let Some(cache) = StatusCache::load().ok().flatten() {
+        if !is_stale(&cache)? {
+            return Ok(());
+        }
+    }
+    if !StatusCache::start_refresh()? {
+        return Ok(());
+    }
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .arg("check")
+        .arg("--update-index")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // Keep the refresh running when the terminal that started it is closed
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    command
+        .spawn()
+        .context("Unable to start background check.")?;
+    Ok(())
+}